@@ -0,0 +1,278 @@
+use std::path::PathBuf;
+use first_test::engine::renderer::BackendType;
+use first_test::engine::camera::{Camera, FovAxis};
+use first_test::engine::stereo::StereoRig;
+use first_test::engine::aov::{Aov, ALL_AOVS};
+use first_test::engine::backends::devices::DeviceSelector;
+use first_test::engine::backends::kernel_source::{KernelSource, KERNEL_DIR};
+use first_test::engine::sampling::AntiAliasing;
+use first_test::engine::tiling::DEFAULT_TILE_SIZE;
+use first_test::engine::tonemapping::ToneMapping;
+
+pub const USAGE: &str = "Usage: first-test [--backend opencl|cpu|multi] [--list-devices] [--platform <index|name>] [--device <index|name>]
+                  [--devices <index|name>,...|all]
+                  [--hot-reload] [--kernel-dir <dir>]
+                  [--width <pixels>] [--height <pixels>] [--output <frame.png|frame.ppm>] [--tile-size <pixels>]
+                  [--spp <samples per pixel>] [--pattern grid|jittered|rotated] [--filter box|tent|gaussian]
+                  [--tonemap clamp|reinhard|aces] [--exposure <stops>]
+                  [--projection perspective|orthographic|equirectangular|fisheye|equisolid] [--fov <degrees>] [--vertical-fov <degrees>] [--view-height <units>]
+                  [--aperture <radius>] [--focus-distance <units>|auto] [--blades <count>]
+                  [--stereo side-by-side|top-bottom|anaglyph] [--interocular <units>] [--convergence <units>]
+                  [--shutter <fraction of a frame>] [--aov depth,position,normal,albedo,objectid|all]
+                  [--stats-csv <stats.csv>]";
+
+pub struct Options {
+    pub backend: BackendType,
+    pub width: u32,
+    pub height: u32,
+    // When set, a single frame is rendered to this file and no window is opened.
+    pub output: Option<PathBuf>,
+    // AOVs written next to the output file.
+    pub aovs: Vec<Aov>,
+    pub tile_size: u32,
+    pub list_devices: bool,
+    pub device_selector: DeviceSelector,
+    // Devices of the multi device backend, every device found when empty.
+    pub device_selectors: Vec<DeviceSelector>,
+    pub kernel_source: KernelSource,
+    pub anti_aliasing: AntiAliasing,
+    pub tone_mapping: ToneMapping,
+    // The camera the scene is first seen from.
+    pub camera: Camera,
+    // Focus on whatever is in the centre of the frame before rendering.
+    pub autofocus: bool,
+    pub stereo: Option<StereoRig>,
+    // Part of the time between two frames the shutter is open in the window, 0 turns off camera motion blur.
+    pub shutter: f32,
+    // Stats of every frame shown in the window are appended to this file.
+    pub stats_csv: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            backend: BackendType::OPENCL,
+            width: 1280,
+            height: 720,
+            output: None,
+            aovs: Vec::new(),
+            tile_size: DEFAULT_TILE_SIZE,
+            list_devices: false,
+            device_selector: DeviceSelector::default(),
+            device_selectors: Vec::new(),
+            kernel_source: KernelSource::default(),
+            anti_aliasing: AntiAliasing::default(),
+            tone_mapping: ToneMapping::default(),
+            camera: Camera::new(90f32, 0.1f32),
+            autofocus: false,
+            stereo: None,
+            shutter: 0f32,
+            stats_csv: None,
+        }
+    }
+}
+
+fn parse_positive(name: &str, value: Option<String>) -> Result<f32, String> {
+    let value = value.ok_or(format!("{} requires a value", name))?;
+    match value.parse::<f32>() {
+        Ok(number) if number.is_finite() && number > 0f32 => Ok(number),
+        _ => Err(format!("{} expects a positive number, got '{}'", name, value)),
+    }
+}
+
+fn parse_dimension(name: &str, value: Option<String>) -> Result<u32, String> {
+    let value = value.ok_or(format!("{} requires a value", name))?;
+    match value.parse::<u32>() {
+        Ok(dimension) if dimension > 0 => Ok(dimension),
+        _ => Err(format!("{} expects a positive number of pixels, got '{}'", name, value)),
+    }
+}
+
+impl Options {
+    pub fn from_args() -> Result<Self, String> {
+        Options::parse(std::env::args().skip(1))
+    }
+
+    // Options from the arguments after the name of the binary.
+    pub fn parse<I: Iterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args;
+        let mut devices = Vec::new();
+        let mut stereo_layout = None;
+        let mut interocular_distance = 1f32;
+        let mut convergence_distance = 50f32;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--backend" => {
+                    let value = args.next().ok_or("--backend requires a value")?;
+                    options.backend = value.parse()?;
+                }
+                "--list-devices" => options.list_devices = true,
+                "--platform" => {
+                    let value = args.next().ok_or("--platform requires an index or name")?;
+                    options.device_selector.platform = Some(value);
+                }
+                "--device" => {
+                    let value = args.next().ok_or("--device requires an index or name")?;
+                    options.device_selector.device = Some(value);
+                }
+                "--devices" => {
+                    let value = args.next().ok_or("--devices requires a list of indices or names, or 'all'")?;
+                    options.backend = BackendType::MULTI;
+                    if value != "all" {
+                        devices = value.split(',').map(|device| device.trim().to_string()).collect();
+                    }
+                }
+                "--hot-reload" => options.kernel_source = KernelSource::DIRECTORY(PathBuf::from(KERNEL_DIR)),
+                "--kernel-dir" => {
+                    let value = args.next().ok_or("--kernel-dir requires a directory")?;
+                    options.kernel_source = KernelSource::DIRECTORY(PathBuf::from(value));
+                }
+                "--spp" => {
+                    let value = args.next().ok_or("--spp requires a value")?;
+                    options.anti_aliasing.samples_per_pixel = match value.parse::<u32>() {
+                        Ok(samples) if samples > 0 => samples,
+                        _ => return Err(format!("--spp expects a positive number, got '{}'", value)),
+                    };
+                }
+                "--pattern" => {
+                    let value = args.next().ok_or("--pattern requires a value")?;
+                    options.anti_aliasing.pattern = value.parse()?;
+                }
+                "--filter" => {
+                    let value = args.next().ok_or("--filter requires a value")?;
+                    options.anti_aliasing.filter = value.parse()?;
+                }
+                "--tonemap" => {
+                    let value = args.next().ok_or("--tonemap requires a value")?;
+                    options.tone_mapping.operator = value.parse()?;
+                }
+                "--exposure" => {
+                    let value = args.next().ok_or("--exposure requires a value")?;
+                    options.tone_mapping.exposure = match value.parse::<f32>() {
+                        Ok(exposure) if exposure.is_finite() => exposure,
+                        _ => return Err(format!("--exposure expects a number of stops, got '{}'", value)),
+                    };
+                }
+                "--projection" => {
+                    let value = args.next().ok_or("--projection requires a value")?;
+                    options.camera.set_projection(value.parse()?);
+                }
+                "--fov" => options.camera.set_fov(parse_positive("--fov", args.next())?, FovAxis::HORIZONTAL),
+                "--vertical-fov" => options.camera.set_fov(parse_positive("--vertical-fov", args.next())?, FovAxis::VERTICAL),
+                "--view-height" => options.camera.set_view_height(parse_positive("--view-height", args.next())?),
+                "--aperture" => options.camera.set_aperture_radius(parse_positive("--aperture", args.next())?),
+                "--focus-distance" => {
+                    let value = args.next().ok_or("--focus-distance requires a distance or 'auto'")?;
+                    if value == "auto" {
+                        options.autofocus = true;
+                    } else {
+                        options.camera.set_focus_distance(parse_positive("--focus-distance", Some(value))?);
+                    }
+                }
+                "--blades" => {
+                    let value = args.next().ok_or("--blades requires a value")?;
+                    options.camera.set_aperture_blades(value.parse::<u32>().map_err(|_| format!("--blades expects a number, got '{}'", value))?);
+                }
+                "--stereo" => {
+                    let value = args.next().ok_or("--stereo requires a layout")?;
+                    stereo_layout = Some(value.parse()?);
+                }
+                "--interocular" => interocular_distance = parse_positive("--interocular", args.next())?,
+                "--convergence" => convergence_distance = parse_positive("--convergence", args.next())?,
+                "--shutter" => {
+                    let value = args.next().ok_or("--shutter requires a value")?;
+                    options.shutter = match value.parse::<f32>() {
+                        Ok(shutter) if (0f32..=1f32).contains(&shutter) => shutter,
+                        _ => return Err(format!("--shutter expects a fraction between 0 and 1, got '{}'", value)),
+                    };
+                }
+                "--width" => options.width = parse_dimension("--width", args.next())?,
+                "--height" => options.height = parse_dimension("--height", args.next())?,
+                "--tile-size" => options.tile_size = parse_dimension("--tile-size", args.next())?,
+                "--output" | "-o" => {
+                    let value = args.next().ok_or("--output requires a file path")?;
+                    options.output = Some(PathBuf::from(value));
+                }
+                "--aov" => {
+                    let value = args.next().ok_or("--aov requires a list of AOVs, or 'all'")?;
+                    options.aovs = match value.as_str() {
+                        "all" => ALL_AOVS.to_vec(),
+                        _ => value.split(',').map(|aov| aov.trim().parse()).collect::<Result<Vec<Aov>, String>>()?,
+                    };
+                }
+                "--stats-csv" => {
+                    let value = args.next().ok_or("--stats-csv requires a file path")?;
+                    options.stats_csv = Some(PathBuf::from(value));
+                }
+                _ => return Err(format!("Unknown argument '{}'", arg)),
+            }
+        }
+        if !options.aovs.is_empty() && options.output.is_none() {
+            return Err("--aov needs an --output file to write the AOVs next to".to_string());
+        }
+        // Checked after parsing, so the order of --fov and --projection doesn't matter.
+        if options.camera.get_fov() > options.camera.get_max_fov() {
+            return Err(format!("The field of view can be at most {} degrees for this projection", options.camera.get_max_fov()));
+        }
+        options.stereo = stereo_layout.map(|layout| StereoRig::new(interocular_distance, convergence_distance, layout));
+        // Done after parsing, so --platform applies no matter where it was given.
+        options.device_selectors = devices.into_iter().map(|device| DeviceSelector {
+            platform: options.device_selector.platform.clone(),
+            device: Some(device),
+        }).collect();
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use first_test::engine::sampling::SamplePattern;
+    use first_test::engine::stereo::StereoLayout;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        Options::parse(args.split_whitespace().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn no_arguments_give_the_defaults() {
+        let options = parse("").unwrap();
+        assert_eq!(options.backend, BackendType::OPENCL);
+        assert_eq!((options.width, options.height), (1280, 720));
+        assert!(options.output.is_none() && options.aovs.is_empty() && options.stereo.is_none());
+    }
+
+    #[test]
+    fn values_are_parsed() {
+        let mut options = parse("--backend cpu --width 640 --height 360 --output frame.png --spp 4 --pattern grid --aov depth,normal --fov 60 --stereo anaglyph --interocular 2").unwrap();
+        assert_eq!(options.backend, BackendType::CPU);
+        assert_eq!((options.width, options.height), (640, 360));
+        assert_eq!(options.output, Some(PathBuf::from("frame.png")));
+        assert_eq!(options.anti_aliasing.samples_per_pixel, 4);
+        assert_eq!(options.anti_aliasing.pattern, SamplePattern::GRID);
+        assert_eq!(options.aovs, vec![Aov::DEPTH, Aov::NORMAL]);
+        assert_eq!(options.camera.get_fov(), 60f32);
+        assert_eq!(options.stereo, Some(StereoRig::new(2f32, 50f32, StereoLayout::ANAGLYPH)));
+    }
+
+    #[test]
+    fn devices_pick_the_multi_device_backend() {
+        let options = parse("--devices 0,GPU --platform 1").unwrap();
+        assert_eq!(options.backend, BackendType::MULTI);
+        let devices: Vec<(Option<String>, Option<String>)> = options.device_selectors.into_iter().map(|selector| (selector.platform, selector.device)).collect();
+        assert_eq!(devices, vec![(Some("1".to_string()), Some("0".to_string())), (Some("1".to_string()), Some("GPU".to_string()))]);
+    }
+
+    #[test]
+    fn bad_arguments_are_rejected() {
+        assert!(parse("--width").is_err());
+        assert!(parse("--width 0").is_err());
+        assert!(parse("--spp -1").is_err());
+        assert!(parse("--shutter 2").is_err());
+        assert!(parse("--aov depth").is_err());
+        assert!(parse("--fov 270").is_err());
+        assert!(parse("--fov 270 --projection fisheye").is_ok());
+        assert!(parse("--frobnicate").is_err());
+    }
+}
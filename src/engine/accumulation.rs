@@ -0,0 +1,72 @@
+// Past this many samples the image is considered converged, and averaging in more would only lose float precision.
+pub const MAX_ACCUMULATED_SAMPLES: u32 = 4096;
+
+// Keeps track of how many jittered samples have been averaged into the accumulation buffer of a backend,
+// and starts over whenever the backend reports that the camera, an object or a light changed since the previous frame.
+#[derive(Default)]
+pub struct Accumulation {
+    enabled: bool,
+    sample_count: u32,
+}
+
+impl Accumulation {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+         }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.reset();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn reset(&mut self) {
+        self.sample_count = 0;
+    }
+
+    pub fn get_sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    // Index of the sample the next frame should render, where 0 restarts the accumulation.
+    // Returns None when the image has converged and the previous output can be shown as is.
+    pub fn next_sample(&mut self, scene_changed: bool) -> Option<u32> {
+        if !self.enabled {
+            return Some(0);
+        }
+        if scene_changed {
+            self.sample_count = 0;
+        } else if self.sample_count >= MAX_ACCUMULATED_SAMPLES {
+            return None;
+        }
+        let sample_index = self.sample_count;
+        self.sample_count += 1;
+        Some(sample_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_start_over_when_the_scene_changed() {
+        let mut accumulation = Accumulation::new();
+        assert_eq!(accumulation.next_sample(false), Some(0));
+        accumulation.set_enabled(true);
+        assert_eq!(accumulation.next_sample(true), Some(0));
+        assert_eq!(accumulation.next_sample(false), Some(1));
+        assert_eq!(accumulation.next_sample(false), Some(2));
+        assert_eq!(accumulation.next_sample(true), Some(0));
+        for _ in 1..MAX_ACCUMULATED_SAMPLES {
+            accumulation.next_sample(false);
+        }
+        assert_eq!(accumulation.next_sample(false), None);
+        assert_eq!(accumulation.next_sample(true), Some(0));
+    }
+}
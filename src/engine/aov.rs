@@ -0,0 +1,108 @@
+use std::str::FromStr;
+
+// Floats per pixel of the AOV buffer the render kernel writes, AOV_LEN in shading.cl.
+pub const AOV_LEN: usize = 11;
+
+// Arbitrary output variables, what the camera rays hit besides the shaded color. Like the color, they're
+// averaged over every sample of a pixel, so edges blend between what's on either side of them.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Aov {
+    // Distance from the camera along its viewing direction, 0 where nothing was hit.
+    DEPTH,
+    // World position of the hit.
+    POSITION,
+    // World space shading normal.
+    NORMAL,
+    // Color of the object before lighting, between 0 and 1.
+    ALBEDO,
+    // Index of the object in the scene, -1 where nothing was hit. Taken from a single sample, so it's never blended.
+    OBJECTID,
+}
+
+pub const ALL_AOVS: [Aov; 5] = [Aov::DEPTH, Aov::POSITION, Aov::NORMAL, Aov::ALBEDO, Aov::OBJECTID];
+
+impl Aov {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Aov::DEPTH => "depth",
+            Aov::POSITION => "position",
+            Aov::NORMAL => "normal",
+            Aov::ALBEDO => "albedo",
+            Aov::OBJECTID => "objectid",
+        }
+    }
+
+    pub fn get_channels(&self) -> usize {
+        match self {
+            Aov::DEPTH | Aov::OBJECTID => 1,
+            _ => 3,
+        }
+    }
+
+    // Where the AOV starts within the AOV_LEN floats of a pixel.
+    fn get_offset(&self) -> usize {
+        match self {
+            Aov::DEPTH => 0,
+            Aov::POSITION => 1,
+            Aov::NORMAL => 4,
+            Aov::ALBEDO => 7,
+            Aov::OBJECTID => 10,
+        }
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "depth" | "z" => Ok(Aov::DEPTH),
+            "position" | "p" => Ok(Aov::POSITION),
+            "normal" | "n" => Ok(Aov::NORMAL),
+            "albedo" => Ok(Aov::ALBEDO),
+            "objectid" | "id" => Ok(Aov::OBJECTID),
+            _ => Err(format!("Unknown AOV '{}', expected 'depth', 'position', 'normal', 'albedo' or 'objectid'", s)),
+        }
+    }
+}
+
+// Every AOV of a width x height frame, AOV_LEN floats per pixel, row by row from the top left.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AovFrame {
+    width: u32,
+    height: u32,
+    data: Vec<f32>,
+}
+
+impl AovFrame {
+    pub fn new(width: u32, height: u32, data: Vec<f32>) -> Self {
+        Self {
+            width,
+            height,
+            data,
+         }
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    // The get_channels floats of aov for every pixel.
+    pub fn get_aov(&self, aov: Aov) -> Vec<f32> {
+        let offset = aov.get_offset();
+        let channels = aov.get_channels();
+        let mut vec = Vec::with_capacity(self.data.len() / AOV_LEN * channels);
+        for pixel in self.data.chunks(AOV_LEN) {
+            vec.extend_from_slice(&pixel[offset..offset + channels]);
+        }
+        vec
+    }
+
+    pub fn get_object_ids(&self) -> Vec<i32> {
+        self.get_aov(Aov::OBJECTID).iter().map(|id| *id as i32).collect()
+    }
+}
@@ -0,0 +1,1084 @@
+use std::thread;
+use std::collections::VecDeque;
+use std::time::Instant;
+use crate::engine::error::RendererError;
+use crate::engine::render::{RenderObject, RenderType, PackedScene};
+use crate::engine::camera::{Camera, CAMERA_VEC_LEN};
+use crate::engine::stereo::{stereo_camera_vec, StereoLayout, StereoRig};
+use crate::engine::renderer::{RenderBackend, TileCallback, MAX_FRAMES_IN_FLIGHT};
+use crate::engine::accumulation::Accumulation;
+use crate::engine::sampling::{AntiAliasing, hash_uint, random_float};
+use crate::engine::tiling::Tile;
+use crate::engine::tonemapping::ToneMapping;
+use crate::engine::stats::FrameStats;
+use crate::engine::aov::AOV_LEN;
+use crate::engine::mesh::{MESH_HEADER_LEN, MESH_BOUNDS_LEN, MESH_HAS_NORMALS};
+
+// Pure Rust port of the OpenCL render kernel. Every function below mirrors its counterpart in
+// the kernel source, so both backends produce the same image for the same scene.
+
+fn normalize_vector(vector: &mut [f32]) {
+    let length = (vector[0] * vector[0] + vector[1] * vector[1] + vector[2] * vector[2]).sqrt();
+    for component in vector.iter_mut().take(3) {
+        *component /= length;
+    }
+}
+
+fn interpolate_cframe(start: &[f32], end: &[f32], time: f32) -> [f32; 12] {
+    let mut cframe = [0f32; 12];
+    for i in 0..12 {
+        cframe[i] = start[i] + (end[i] - start[i]) * time;
+    }
+    normalize_vector(&mut cframe[3..6]);
+    let projection = cframe[6] * cframe[3] + cframe[7] * cframe[4] + cframe[8] * cframe[5];
+    for i in 0..3 {
+        cframe[6 + i] -= projection * cframe[3 + i];
+    }
+    normalize_vector(&mut cframe[6..9]);
+    cframe[9] = cframe[4] * cframe[8] - cframe[5] * cframe[7];
+    cframe[10] = cframe[5] * cframe[6] - cframe[3] * cframe[8];
+    cframe[11] = cframe[3] * cframe[7] - cframe[4] * cframe[6];
+    cframe
+}
+
+fn camera_ray_cframe(camera: &[f32], origin: &[f32; 3], direction: &[f32; 3]) -> [f32; 12] {
+    let length = (direction[0] * direction[0] + direction[1] * direction[1] + direction[2] * direction[2]).sqrt();
+    let mut ray_cframe = [0f32; 12];
+    for i in 0..3 {
+        ray_cframe[i] = camera[i] + origin[0] * camera[3 + i] + origin[1] * camera[6 + i] + origin[2] * camera[9 + i];
+        let world_direction = direction[0] * camera[3 + i] + direction[1] * camera[6 + i] + direction[2] * camera[9 + i];
+        ray_cframe[5 + i * 3] = -world_direction / length;
+    }
+    ray_cframe
+}
+
+fn solve_quadratic(a: f32, b: f32, c: f32) -> Option<(f32, f32)> {
+    let discr = b * b - 4f32 * a * c;
+    if discr < 0f32 {
+        return None;
+    } else if discr == 0f32 {
+        let x = -0.5 * b / a;
+        return Some((x, x));
+    }
+    let q = if b > 0f32 {
+        -0.5 * (b + discr.sqrt())
+    } else {
+        -0.5 * (b - discr.sqrt())
+    };
+    Some((q / a, c / q))
+}
+
+// Ray from origin along direction, laid out like the rays of the kernel: the reversed direction is the last column.
+pub fn ray_cframe(origin: &[f32; 3], direction: &[f32; 3]) -> [f32; 12] {
+    [origin[0], origin[1], origin[2], 0f32, 0f32, -direction[0], 0f32, 0f32, -direction[1], 0f32, 0f32, -direction[2]]
+}
+
+// Distance along the ray to the nearest hit in front of its origin, or -1 when it misses.
+pub fn intersect_sphere(sphere_cframe: &[f32], sphere_radius: f32, ray_cframe: &[f32; 12]) -> f32 {
+    let lx = sphere_cframe[0] - ray_cframe[0];
+    let ly = sphere_cframe[1] - ray_cframe[1];
+    let lz = sphere_cframe[2] - ray_cframe[2];
+
+    let a = ray_cframe[5] * ray_cframe[5] + ray_cframe[8] * ray_cframe[8] + ray_cframe[11] * ray_cframe[11];
+    let b = 2f32 * (ray_cframe[5] * lx + ray_cframe[8] * ly + ray_cframe[11] * lz);
+    let c = lx * lx + ly * ly + lz * lz - sphere_radius * sphere_radius;
+    match solve_quadratic(a, b, c) {
+        Some((t0, t1)) => {
+            if t0 > 0f32 && t1 > 0f32 {
+                return t0.min(t1);
+            } else if t0 > 0f32 {
+                return t0;
+            } else if t1 > 0f32 {
+                return t1;
+            }
+            -1f32
+        }
+        None => -1f32,
+    }
+}
+
+// Infinite plane through the position of plane_cframe, facing along its up vector, which is the second row.
+// Distance along the ray to the hit, or -1 when it misses.
+pub fn intersect_plane(plane_cframe: &[f32], ray_cframe: &[f32; 12]) -> f32 {
+    let denom = -(plane_cframe[6] * ray_cframe[5] + plane_cframe[7] * ray_cframe[8] + plane_cframe[8] * ray_cframe[11]);
+    // Rays running along the plane never hit it.
+    if denom.abs() < 1e-6f32 {
+        return -1f32;
+    }
+    let distance = plane_cframe[6] * (plane_cframe[0] - ray_cframe[0]) + plane_cframe[7] * (plane_cframe[1] - ray_cframe[1]) + plane_cframe[8] * (plane_cframe[2] - ray_cframe[2]);
+    let t = distance / denom;
+    if t > 0f32 {
+        return t;
+    }
+    -1f32
+}
+
+// Box with the given half_size along the axes of its cframe, which are its rows. Slab test in the space of the box:
+// the ray is inside between where it enters the last slab and leaves the first one.
+pub fn intersect_cuboid(cuboid_cframe: &[f32], half_size: &[f32], ray_cframe: &[f32; 12]) -> f32 {
+    let mut t_near = f32::NEG_INFINITY;
+    let mut t_far = f32::INFINITY;
+    for axis in 0..3 {
+        let row = &cuboid_cframe[3 + axis * 3..6 + axis * 3];
+        let origin = (ray_cframe[0] - cuboid_cframe[0]) * row[0] + (ray_cframe[1] - cuboid_cframe[1]) * row[1] + (ray_cframe[2] - cuboid_cframe[2]) * row[2];
+        let direction = -(ray_cframe[5] * row[0] + ray_cframe[8] * row[1] + ray_cframe[11] * row[2]);
+        if direction.abs() < 1e-8f32 {
+            // Parallel to the slab, it either always or never is between its sides.
+            if origin.abs() > half_size[axis] {
+                return -1f32;
+            }
+            continue;
+        }
+        let t0 = (-half_size[axis] - origin) / direction;
+        let t1 = (half_size[axis] - origin) / direction;
+        t_near = t_near.max(t0.min(t1));
+        t_far = t_far.min(t0.max(t1));
+    }
+    if t_near > t_far || t_far <= 0f32 {
+        return -1f32;
+    }
+    // A ray starting inside the box hits it where it leaves.
+    if t_near > 0f32 {
+        return t_near;
+    }
+    t_far
+}
+
+// Normal of the face of the box the point edge_pos lies on, the axis it's furthest out on relative to the size.
+pub fn cuboid_normal(cuboid_cframe: &[f32], half_size: &[f32], edge_pos: &[f32; 3]) -> [f32; 3] {
+    let mut face_axis = 0;
+    let mut face_side = 1f32;
+    let mut furthest = -1f32;
+    for axis in 0..3 {
+        let row = &cuboid_cframe[3 + axis * 3..6 + axis * 3];
+        let local = (edge_pos[0] - cuboid_cframe[0]) * row[0] + (edge_pos[1] - cuboid_cframe[1]) * row[1] + (edge_pos[2] - cuboid_cframe[2]) * row[2];
+        let relative = local.abs() / half_size[axis];
+        if relative > furthest {
+            furthest = relative;
+            face_axis = axis;
+            face_side = if local < 0f32 { -1f32 } else { 1f32 };
+        }
+    }
+    [cuboid_cframe[3 + face_axis * 3] * face_side, cuboid_cframe[4 + face_axis * 3] * face_side, cuboid_cframe[5 + face_axis * 3] * face_side]
+}
+
+// Origin and direction of the ray in the space of the object at cframe, whose rows are its axes.
+fn ray_to_local(cframe: &[f32], ray_cframe: &[f32; 12]) -> ([f32; 3], [f32; 3]) {
+    let mut origin = [0f32; 3];
+    let mut direction = [0f32; 3];
+    for axis in 0..3 {
+        let row = &cframe[3 + axis * 3..6 + axis * 3];
+        origin[axis] = (ray_cframe[0] - cframe[0]) * row[0] + (ray_cframe[1] - cframe[1]) * row[1] + (ray_cframe[2] - cframe[2]) * row[2];
+        direction[axis] = -(ray_cframe[5] * row[0] + ray_cframe[8] * row[1] + ray_cframe[11] * row[2]);
+    }
+    (origin, direction)
+}
+
+// Whether the ray passes through the box between the corners bounds[0..3] and bounds[3..6] in front of its origin.
+fn hits_bounds(origin: &[f32; 3], direction: &[f32; 3], bounds: &[f32]) -> bool {
+    let mut t_near = 0f32;
+    let mut t_far = f32::INFINITY;
+    for axis in 0..3 {
+        // Dividing by a zero direction gives infinities, which min and max handle, and NaNs, which they ignore.
+        let inverse = 1f32 / direction[axis];
+        let t0 = (bounds[axis] - origin[axis]) * inverse;
+        let t1 = (bounds[3 + axis] - origin[axis]) * inverse;
+        t_near = t_near.max(t0.min(t1));
+        t_far = t_far.min(t0.max(t1));
+    }
+    t_near <= t_far
+}
+
+// Nearest triangle of the mesh hit by the ray with the watertight test of Woop, Benthin and Wald, see trace_mesh in
+// the kernel for the layout of props and uint_props. Returns the index of the triangle, the distance along the ray
+// and the barycentric weights of the three vertices of the triangle.
+pub fn trace_mesh(cframe: &[f32], props: &[f32], uint_props: &[u32], ray_cframe: &[f32; 12]) -> Option<(usize, f32, [f32; 3])> {
+    let (origin, direction) = ray_to_local(cframe, ray_cframe);
+    if !hits_bounds(&origin, &direction, &props[0..MESH_BOUNDS_LEN]) {
+        return None;
+    }
+    let triangle_count = uint_props[0] as usize;
+    let positions = &props[MESH_BOUNDS_LEN..];
+    let indices = &uint_props[MESH_HEADER_LEN..];
+    // The ray is turned so it runs along z, which is the axis it's longest along, and sheared to be parallel to it.
+    let mut kz = 0;
+    if direction[1].abs() > direction[kz].abs() {
+        kz = 1;
+    }
+    if direction[2].abs() > direction[kz].abs() {
+        kz = 2;
+    }
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if direction[kz] < 0f32 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+    let shear_x = direction[kx] / direction[kz];
+    let shear_y = direction[ky] / direction[kz];
+    let shear_z = 1f32 / direction[kz];
+    let mut hit = None;
+    let mut t = f32::INFINITY;
+    for i in 0..triangle_count {
+        let mut vertex = [[0f32; 3]; 3];
+        for j in 0..3 {
+            let index = indices[i * 3 + j] as usize;
+            for axis in 0..3 {
+                vertex[j][axis] = positions[index * 3 + axis] - origin[axis];
+            }
+        }
+        let ax = vertex[0][kx] - shear_x * vertex[0][kz];
+        let ay = vertex[0][ky] - shear_y * vertex[0][kz];
+        let bx = vertex[1][kx] - shear_x * vertex[1][kz];
+        let by = vertex[1][ky] - shear_y * vertex[1][kz];
+        let cx = vertex[2][kx] - shear_x * vertex[2][kz];
+        let cy = vertex[2][ky] - shear_y * vertex[2][kz];
+        let u = cx * by - cy * bx;
+        let v = ax * cy - ay * cx;
+        let w = bx * ay - by * ax;
+        // The ray only passes through the triangle when it's on the same side of all three edges.
+        if (u < 0f32 || v < 0f32 || w < 0f32) && (u > 0f32 || v > 0f32 || w > 0f32) {
+            continue;
+        }
+        let det = u + v + w;
+        if det == 0f32 {
+            continue;
+        }
+        let local_t = (u * vertex[0][kz] + v * vertex[1][kz] + w * vertex[2][kz]) * shear_z / det;
+        if local_t > 0f32 && local_t < t {
+            t = local_t;
+            hit = Some((i, local_t, [u / det, v / det, w / det]));
+        }
+    }
+    hit
+}
+
+// Normal where the ray hits triangle of the mesh at the barycentric weights, blended between the vertex normals when the
+// mesh has them, which decide what is outside. Otherwise it's the normal of the triangle facing the ray, so both sides
+// of a triangle are lit the same.
+pub fn mesh_normal(cframe: &[f32], props: &[f32], uint_props: &[u32], ray_cframe: &[f32; 12], triangle: usize, weights: &[f32; 3]) -> [f32; 3] {
+    let vertex_count = uint_props[1] as usize;
+    let flags = uint_props[2];
+    let positions = &props[MESH_BOUNDS_LEN..];
+    let normals = &positions[vertex_count * 3..];
+    let indices = &uint_props[MESH_HEADER_LEN..];
+    let index = [indices[triangle * 3] as usize, indices[triangle * 3 + 1] as usize, indices[triangle * 3 + 2] as usize];
+    let mut edge1 = [0f32; 3];
+    let mut edge2 = [0f32; 3];
+    for axis in 0..3 {
+        edge1[axis] = positions[index[1] * 3 + axis] - positions[index[0] * 3 + axis];
+        edge2[axis] = positions[index[2] * 3 + axis] - positions[index[0] * 3 + axis];
+    }
+    let face_normal = [edge1[1] * edge2[2] - edge1[2] * edge2[1], edge1[2] * edge2[0] - edge1[0] * edge2[2], edge1[0] * edge2[1] - edge1[1] * edge2[0]];
+    let mut local_normal = face_normal;
+    if flags & MESH_HAS_NORMALS != 0 {
+        for axis in 0..3 {
+            local_normal[axis] = weights[0] * normals[index[0] * 3 + axis] + weights[1] * normals[index[1] * 3 + axis] + weights[2] * normals[index[2] * 3 + axis];
+        }
+    } else {
+        let (_, direction) = ray_to_local(cframe, ray_cframe);
+        if face_normal[0] * direction[0] + face_normal[1] * direction[1] + face_normal[2] * direction[2] > 0f32 {
+            local_normal = [-local_normal[0], -local_normal[1], -local_normal[2]];
+        }
+    }
+    let mut normal = [0f32; 3];
+    for axis in 0..3 {
+        normal[axis] = local_normal[0] * cframe[3 + axis] + local_normal[1] * cframe[6 + axis] + local_normal[2] * cframe[9 + axis];
+    }
+    normalize_vector(&mut normal);
+    normal
+}
+
+// Where the shadow ray of a hit on triangle of the mesh starts, the hit moved out onto the curved surface the vertex
+// normals describe, see mesh_shadow_origin in the kernel.
+pub fn mesh_shadow_origin(cframe: &[f32], props: &[f32], uint_props: &[u32], triangle: usize, weights: &[f32; 3], edge_pos: &[f32; 3]) -> [f32; 3] {
+    let vertex_count = uint_props[1] as usize;
+    let flags = uint_props[2];
+    if flags & MESH_HAS_NORMALS == 0 {
+        return *edge_pos;
+    }
+    let positions = &props[MESH_BOUNDS_LEN..];
+    let normals = &positions[vertex_count * 3..];
+    let indices = &uint_props[MESH_HEADER_LEN..];
+    let mut local = [0f32; 3];
+    for axis in 0..3 {
+        let row = &cframe[3 + axis * 3..6 + axis * 3];
+        local[axis] = (edge_pos[0] - cframe[0]) * row[0] + (edge_pos[1] - cframe[1]) * row[1] + (edge_pos[2] - cframe[2]) * row[2];
+    }
+    // The hit projected onto the tangent plane of every vertex, blended like the normals.
+    let mut curved = [0f32; 3];
+    let mut shading_normal = [0f32; 3];
+    for j in 0..3 {
+        let index = indices[triangle * 3 + j] as usize;
+        let vertex = &positions[index * 3..index * 3 + 3];
+        let normal = &normals[index * 3..index * 3 + 3];
+        let length_squared = normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2];
+        let mut distance = 0f32;
+        if length_squared > 0f32 {
+            distance = ((local[0] - vertex[0]) * normal[0] + (local[1] - vertex[1]) * normal[1] + (local[2] - vertex[2]) * normal[2]) / length_squared;
+        }
+        for axis in 0..3 {
+            curved[axis] += weights[j] * (local[axis] - distance * normal[axis]);
+            shading_normal[axis] += weights[j] * normal[axis];
+        }
+    }
+    // Where the surface curves inwards the triangle already lies above it.
+    if (curved[0] - local[0]) * shading_normal[0] + (curved[1] - local[1]) * shading_normal[1] + (curved[2] - local[2]) * shading_normal[2] <= 0f32 {
+        return *edge_pos;
+    }
+    let mut origin = [0f32; 3];
+    for axis in 0..3 {
+        origin[axis] = cframe[axis] + curved[0] * cframe[3 + axis] + curved[1] * cframe[6 + axis] + curved[2] * cframe[9 + axis];
+    }
+    origin
+}
+
+// Part of an object a ray hit: the index of the triangle of a mesh and the barycentric weights of its vertices.
+// Types made of a single surface always have primitive -1.
+#[derive(Copy, Clone)]
+struct PrimitiveHit {
+    primitive: i32,
+    weights: [f32; 3],
+}
+
+const NO_PRIMITIVE: PrimitiveHit = PrimitiveHit { primitive: -1, weights: [0f32; 3] };
+
+// Hit of the ray with an object of render_type at cframe, props and uint_props are the parameters of the object.
+fn intersect_object(render_type: u8, cframe: &[f32], props: &[f32], uint_props: &[u32], ray_cframe: &[f32; 12]) -> (f32, PrimitiveHit) {
+    if render_type == RenderType::PLANE as u8 {
+        return (intersect_plane(cframe, ray_cframe), NO_PRIMITIVE);
+    } else if render_type == RenderType::SPHERE as u8 {
+        return (intersect_sphere(cframe, props[0], ray_cframe), NO_PRIMITIVE);
+    } else if render_type == RenderType::CUBOID as u8 {
+        return (intersect_cuboid(cframe, props, ray_cframe), NO_PRIMITIVE);
+    } else if render_type == RenderType::MESH as u8 {
+        match trace_mesh(cframe, props, uint_props, ray_cframe) {
+            Some((triangle, t, weights)) => return (t, PrimitiveHit { primitive: triangle as i32, weights }),
+            None => return (-1f32, NO_PRIMITIVE),
+        }
+    }
+    (-1f32, NO_PRIMITIVE)
+}
+
+// Normal of an object of render_type at cframe, at the point edge_pos where ray_cframe hits its surface, on the
+// primitive intersect_object found. Only called for types intersect_object can hit.
+fn object_normal(render_type: u8, cframe: &[f32], props: &[f32], uint_props: &[u32], ray_cframe: &[f32; 12], edge_pos: &[f32; 3], hit: &PrimitiveHit) -> [f32; 3] {
+    if render_type == RenderType::PLANE as u8 {
+        return [cframe[6], cframe[7], cframe[8]];
+    } else if render_type == RenderType::CUBOID as u8 {
+        return cuboid_normal(cframe, props, edge_pos);
+    } else if render_type == RenderType::MESH as u8 {
+        return mesh_normal(cframe, props, uint_props, ray_cframe, hit.primitive as usize, &hit.weights);
+    }
+    let normal = [edge_pos[0] - cframe[0], edge_pos[1] - cframe[1], edge_pos[2] - cframe[2]];
+    let normal_size = ((normal[0] * normal[0]) + (normal[1] * normal[1]) + (normal[2] * normal[2])).sqrt();
+    [normal[0] / normal_size, normal[1] / normal_size, normal[2] / normal_size]
+}
+
+fn intersect_objects(scene: &PackedScene, ray_cframe: &[f32; 12], time: f32) -> (i32, f32, PrimitiveHit) {
+    let mut t = 9999999f32;
+    let mut index_found = -1;
+    let mut primitive_found = NO_PRIMITIVE;
+    for i in 0..(scene.object_amnt as usize) {
+        let cframe = interpolate_cframe(&scene.cframes[i * 12..], &scene.end_cframes[i * 12..], time);
+        let (local_t, hit) = intersect_object(scene.render_types[i], &cframe, scene.get_object_props(i), scene.get_object_uint_props(i), ray_cframe);
+        if local_t > 0f32 && local_t < t {
+            t = local_t;
+            index_found = i as i32;
+            primitive_found = hit;
+        }
+    }
+    (index_found, t, primitive_found)
+}
+
+fn calculate_normal_vector(scene: &PackedScene, object_index: usize, time: f32, ray_cframe: &[f32; 12], edge_pos: &[f32; 3], hit: &PrimitiveHit) -> [f32; 3] {
+    let object_cframe = interpolate_cframe(&scene.cframes[object_index * 12..], &scene.end_cframes[object_index * 12..], time);
+    object_normal(scene.render_types[object_index], &object_cframe, scene.get_object_props(object_index), scene.get_object_uint_props(object_index), ray_cframe, edge_pos, hit)
+}
+
+// AOVs where nothing was hit.
+fn missed_aov() -> [f32; AOV_LEN] {
+    let mut aov = [0f32; AOV_LEN];
+    aov[AOV_LEN - 1] = -1f32;
+    aov
+}
+
+fn render_pixel(scene: &PackedScene, ray_cframe: &[f32; 12], time: f32, directionlight_direction: &[f32], directionlight_color: &[u8]) -> ([f32; 3], [f32; AOV_LEN]) {
+    let mut aov = missed_aov();
+    let (intersection_index, t, hit) = intersect_objects(scene, ray_cframe, time);
+    if intersection_index < 0 {
+        return ([0f32; 3], aov);
+    }
+
+    let edge_pos = [ray_cframe[0] - (ray_cframe[5] * t), ray_cframe[1] - (ray_cframe[8] * t), ray_cframe[2] - (ray_cframe[11] * t)];
+    let normal = calculate_normal_vector(scene, intersection_index as usize, time, ray_cframe, &edge_pos, &hit);
+    let color = &scene.colors[(intersection_index as usize) * 3..];
+    for i in 0..3 {
+        aov[1 + i] = edge_pos[i];
+        aov[4 + i] = normal[i];
+        aov[7 + i] = color[i] as f32 / 255f32;
+    }
+    aov[AOV_LEN - 1] = intersection_index as f32;
+    let mut shadow_origin = edge_pos;
+    // Only meshes have primitives, see intersect_object.
+    if hit.primitive >= 0 {
+        let index = intersection_index as usize;
+        let cframe = interpolate_cframe(&scene.cframes[index * 12..], &scene.end_cframes[index * 12..], time);
+        shadow_origin = mesh_shadow_origin(&cframe, scene.get_object_props(index), scene.get_object_uint_props(index), hit.primitive as usize, &hit.weights, &edge_pos);
+    }
+    // Same self-intersection correction as the kernel, see render_pixel there.
+    let correction_factor = 0.01f32;
+    let edge_to_dir_light = [shadow_origin[0] + (normal[0] * correction_factor), shadow_origin[1] + (normal[1] * correction_factor), shadow_origin[2] + (normal[2] * correction_factor),
+                             0.0, 0.0, directionlight_direction[0],
+                             0.0, 0.0, directionlight_direction[1],
+                             0.0, 0.0, directionlight_direction[2]];
+    let (dl_int_index, _, _) = intersect_objects(scene, &edge_to_dir_light, time);
+    // Same exception as the kernel, only meshes can shadow themselves.
+    let self_hit = dl_int_index == intersection_index && scene.render_types[intersection_index as usize] != RenderType::MESH as u8;
+    if dl_int_index >= 0 && !self_hit {
+        return ([0f32; 3], aov);
+    }
+
+    let diffuse_factor = (normal[0] * (-directionlight_direction[0]) + normal[1] * (-directionlight_direction[1]) + normal[2] * (-directionlight_direction[2])).max(0f32);
+    let mut pixel = [0f32; 3];
+    for i in 0..3 {
+        let directional_diffuse_light_color = directionlight_color[i] as f32 * diffuse_factor / 255f32;
+        pixel[i] = color[i] as f32 * directional_diffuse_light_color;
+    }
+    (pixel, aov)
+}
+
+// Camera parameters that stay the same for every pixel of a frame.
+struct FrameCamera<'a> {
+    // The left eye followed by the right one, see stereo_camera_vec.
+    camera: &'a [f32],
+    stereo_layout: u8,
+    camera_width: f32,
+    camera_height: f32,
+    focal_length: f32,
+    width: usize,
+    height: usize,
+}
+
+impl<'a> FrameCamera<'a> {
+    fn new(camera_vec: &'a [f32], stereo_layout: u8, camera: &mut Camera, width: u32, height: u32) -> Self {
+        let (camera_width, camera_height) = camera.get_camera_dimensions(width, height);
+        Self {
+            camera: camera_vec,
+            stereo_layout,
+            camera_width,
+            camera_height,
+            focal_length: camera.get_focal_length(),
+            width: width as usize,
+            height: height as usize,
+         }
+    }
+}
+
+// The scene of a frame and the light it's lit by.
+struct FrameScene<'a> {
+    scene: &'a PackedScene,
+    directionlight_direction: &'a [f32],
+    directionlight_color: &'a [u8],
+}
+
+// Which sample of a frame is rendered and how its pixels are sampled.
+struct FrameSampling<'a> {
+    sample_index: u32,
+    anti_aliasing: &'a AntiAliasing,
+}
+
+// The part of the frame one eye sees and the camera of that eye, see select_view.
+#[derive(Copy, Clone)]
+struct View<'a> {
+    width: usize,
+    height: usize,
+    camera: &'a [f32],
+}
+
+fn primary_ray(px: f32, py: f32, view: &View, frame_camera: &FrameCamera) -> Option<([f32; 3], [f32; 3])> {
+    let camera = view.camera;
+    let cam_x = - (frame_camera.camera_width / 2f32) + ((px / view.width as f32) * frame_camera.camera_width);
+    let cam_y = - (frame_camera.camera_height / 2f32) + ((py / view.height as f32) * frame_camera.camera_height);
+    let projection = camera[13] as u8;
+    match projection {
+        1 => Some(([cam_x, -cam_y, 0f32], [0f32, 0f32, -1f32])),
+        2 => Some(([0f32; 3], [(-cam_y).cos() * cam_x.sin(), (-cam_y).sin(), -(-cam_y).cos() * cam_x.cos()])),
+        3 | 4 => {
+            let half_fov = camera[12].to_radians() / 2f32;
+            let r = (cam_x * cam_x + cam_y * cam_y).sqrt();
+            if r > half_fov {
+                return None;
+            }
+            let mut theta = r;
+            if projection == 4 {
+                theta = 2f32 * (r / half_fov * (half_fov / 2f32).sin()).asin();
+            }
+            if r > 0f32 {
+                return Some(([0f32; 3], [theta.sin() * cam_x / r, theta.sin() * -cam_y / r, -theta.cos()]));
+            }
+            Some(([0f32; 3], [0f32, 0f32, -1f32]))
+        }
+        _ => Some(([0f32; 3], [cam_x + camera[17] * frame_camera.focal_length, -cam_y, -frame_camera.focal_length])),
+    }
+}
+
+fn sample_lens(blades: u32, rng_state: &mut u32) -> (f32, f32) {
+    let u = random_float(rng_state);
+    let v = random_float(rng_state);
+    if blades < 3 {
+        let r = u.sqrt();
+        let phi = 2f32 * std::f32::consts::PI * v;
+        return (r * phi.cos(), r * phi.sin());
+    }
+    let segment = (u * blades as f32).floor();
+    let r = (u * blades as f32 - segment).sqrt();
+    let angle0 = 2f32 * std::f32::consts::PI * segment / blades as f32;
+    let angle1 = 2f32 * std::f32::consts::PI * (segment + 1f32) / blades as f32;
+    (r * ((1f32 - v) * angle0.cos() + v * angle1.cos()), r * ((1f32 - v) * angle0.sin() + v * angle1.sin()))
+}
+
+fn apply_lens(camera: &[f32], rng_state: &mut u32, origin: &mut [f32; 3], direction: &mut [f32; 3]) {
+    let aperture_radius = camera[14];
+    if aperture_radius <= 0f32 || camera[13] as u8 >= 2 {
+        return;
+    }
+    let focus_distance = camera[15];
+    let (lens_x, lens_y) = sample_lens(camera[16] as u32, rng_state);
+    let t = focus_distance / -direction[2];
+    let focus_point = [origin[0] + direction[0] * t, origin[1] + direction[1] * t, origin[2] + direction[2] * t];
+    origin[0] += lens_x * aperture_radius;
+    origin[1] += lens_y * aperture_radius;
+    for i in 0..3 {
+        direction[i] = focus_point[i] - origin[i];
+    }
+}
+
+fn trace_camera_ray(px: f32, py: f32, rng_state: &mut u32, view: &View, frame_camera: &FrameCamera, frame_scene: &FrameScene) -> ([f32; 3], [f32; AOV_LEN]) {
+    let camera = view.camera;
+    let time = random_float(rng_state);
+    let (mut origin, mut direction) = match primary_ray(px, py, view, frame_camera) {
+        Some(ray) => ray,
+        None => return ([0f32; 3], missed_aov()),
+    };
+    apply_lens(camera, rng_state, &mut origin, &mut direction);
+    let camera_cframe = interpolate_cframe(camera, &camera[18..], time);
+    let ray_cframe = camera_ray_cframe(&camera_cframe, &origin, &direction);
+    let (color, mut aov) = render_pixel(frame_scene.scene, &ray_cframe, time, frame_scene.directionlight_direction, frame_scene.directionlight_color);
+    if aov[AOV_LEN - 1] >= 0f32 {
+        aov[0] = -((aov[1] - camera_cframe[0]) * camera_cframe[9] + (aov[2] - camera_cframe[1]) * camera_cframe[10] + (aov[3] - camera_cframe[2]) * camera_cframe[11]);
+    }
+    (color, aov)
+}
+
+fn dubois_anaglyph(left: &[f32; 3], right: &[f32; 3]) -> [f32; 3] {
+    [
+        (0.437 * left[0] + 0.449 * left[1] + 0.164 * left[2] - 0.011 * right[0] - 0.032 * right[1] - 0.007 * right[2]).max(0f32),
+        (-0.062 * left[0] - 0.062 * left[1] - 0.024 * left[2] + 0.377 * right[0] + 0.761 * right[1] + 0.009 * right[2]).max(0f32),
+        (-0.048 * left[0] - 0.050 * left[1] - 0.017 * left[2] - 0.026 * right[0] - 0.093 * right[1] + 1.234 * right[2]).max(0f32),
+    ]
+}
+
+fn trace_view_ray(px: f32, py: f32, rng_state: &mut u32, view: &View, frame_camera: &FrameCamera, frame_scene: &FrameScene) -> ([f32; 3], [f32; AOV_LEN]) {
+    if frame_camera.stereo_layout != StereoLayout::ANAGLYPH as u8 {
+        return trace_camera_ray(px, py, rng_state, view, frame_camera, frame_scene);
+    }
+    let mut right_rng_state = *rng_state;
+    let right_view = View { camera: &view.camera[CAMERA_VEC_LEN..], ..*view };
+    let (left, aov) = trace_camera_ray(px, py, rng_state, view, frame_camera, frame_scene);
+    let (right, _) = trace_camera_ray(px, py, &mut right_rng_state, &right_view, frame_camera, frame_scene);
+    (dubois_anaglyph(&left, &right), aov)
+}
+
+// The view of the eye that shows the point (px, py) of the frame, and the point within that view.
+fn select_view<'a>(px: f32, py: f32, frame_camera: &FrameCamera<'a>) -> (f32, f32, View<'a>) {
+    let mut px = px;
+    let mut py = py;
+    let mut width = frame_camera.width;
+    let mut height = frame_camera.height;
+    let mut camera = frame_camera.camera;
+    if frame_camera.stereo_layout == StereoLayout::SIDEBYSIDE as u8 {
+        width = frame_camera.width / 2;
+        if px >= width as f32 {
+            px -= width as f32;
+            width = frame_camera.width - width;
+            camera = &frame_camera.camera[CAMERA_VEC_LEN..];
+        }
+    } else if frame_camera.stereo_layout == StereoLayout::TOPBOTTOM as u8 {
+        height = frame_camera.height / 2;
+        if py >= height as f32 {
+            py -= height as f32;
+            height = frame_camera.height - height;
+            camera = &frame_camera.camera[CAMERA_VEC_LEN..];
+        }
+    }
+    (px, py, View { width, height, camera })
+}
+
+// What a primary ray hit, see cast_primary_ray.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RayHit {
+    pub object_index: usize,
+    // Along the ray, from where it left the camera.
+    pub distance: f32,
+    // Along the viewing direction of the camera, which is what the depth AOV holds.
+    pub depth: f32,
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+}
+
+// Nearest object on the primary ray through the point (px, py) of a width x height frame, in pixels from its
+// top left corner. The ray is the one the kernel traces through that point, through the centre of the lens
+// when the shutter opens. With a stereo rig it's the ray of the eye that shows the point, the left one for anaglyphs.
+pub fn cast_primary_ray(camera: &mut Camera, stereo: &Option<StereoRig>, scene: &PackedScene, px: f32, py: f32, width: u32, height: u32) -> Option<RayHit> {
+    let (camera_vec, stereo_layout) = stereo_camera_vec(camera, stereo);
+    let frame_camera = FrameCamera::new(&camera_vec, stereo_layout, camera, width, height);
+    let (px, py, view) = select_view(px, py, &frame_camera);
+    let (origin, direction) = primary_ray(px, py, &view, &frame_camera)?;
+    let camera_cframe = interpolate_cframe(view.camera, &view.camera[18..], 0f32);
+    let ray_cframe = camera_ray_cframe(&camera_cframe, &origin, &direction);
+    let (intersection_index, t, hit) = intersect_objects(scene, &ray_cframe, 0f32);
+    if intersection_index < 0 {
+        return None;
+    }
+    let position = [ray_cframe[0] - (ray_cframe[5] * t), ray_cframe[1] - (ray_cframe[8] * t), ray_cframe[2] - (ray_cframe[11] * t)];
+    let length = (direction[0] * direction[0] + direction[1] * direction[1] + direction[2] * direction[2]).sqrt();
+    Some(RayHit {
+        object_index: intersection_index as usize,
+        distance: t,
+        depth: t * -direction[2] / length,
+        position,
+        normal: calculate_normal_vector(scene, intersection_index as usize, 0f32, &ray_cframe, &position, &hit),
+    })
+}
+
+fn render_sample(x: u32, y: u32, sampling: &FrameSampling, frame_camera: &FrameCamera, frame_scene: &FrameScene) -> ([f32; 3], [f32; AOV_LEN]) {
+    let sample_index = sampling.sample_index;
+    let anti_aliasing = sampling.anti_aliasing;
+    let pixel_id = y.wrapping_mul(frame_camera.width as u32).wrapping_add(x);
+    let mut rng_state = hash_uint(pixel_id ^ hash_uint(sample_index));
+    let (x, y, view) = select_view(x as f32, y as f32, frame_camera);
+    if anti_aliasing.samples_per_pixel <= 1 && sample_index == 0 {
+        return trace_view_ray(x + 0.5, y + 0.5, &mut rng_state, &view, frame_camera, frame_scene);
+    }
+
+    let mut shift_x = 0f32;
+    let mut shift_y = 0f32;
+    if sample_index > 0 {
+        shift_x = random_float(&mut rng_state);
+        shift_y = random_float(&mut rng_state);
+    }
+    let radius = anti_aliasing.filter.get_radius();
+    let mut pixel_color = [0f32; 3];
+    let mut pixel_aov = [0f32; AOV_LEN];
+    let mut weight_sum = 0f32;
+    for sample in 0..anti_aliasing.samples_per_pixel {
+        let (u, v) = anti_aliasing.pattern.sample_position(sample, anti_aliasing.samples_per_pixel, &mut rng_state);
+        let u = u + shift_x;
+        let v = v + shift_y;
+        let dx = ((u - u.floor()) - 0.5) * 2f32 * radius;
+        let dy = ((v - v.floor()) - 0.5) * 2f32 * radius;
+        let weight = anti_aliasing.filter.weight(dx, dy);
+        let (sample_color, sample_aov) = trace_view_ray(x + 0.5 + dx, y + 0.5 + dy, &mut rng_state, &view, frame_camera, frame_scene);
+        for i in 0..3 {
+            pixel_color[i] += sample_color[i] * weight;
+        }
+        for i in 0..AOV_LEN - 1 {
+            pixel_aov[i] += sample_aov[i] * weight;
+        }
+        if sample == 0 {
+            pixel_aov[AOV_LEN - 1] = sample_aov[AOV_LEN - 1];
+        }
+        weight_sum += weight;
+    }
+    if weight_sum > 0f32 {
+        for component in pixel_color.iter_mut() {
+            *component /= weight_sum;
+        }
+        for component in pixel_aov.iter_mut().take(AOV_LEN - 1) {
+            *component /= weight_sum;
+        }
+    }
+    (pixel_color, pixel_aov)
+}
+
+fn accumulate_pixel(hdr: &mut [f32], sample_index: u32, color: &[f32; 3]) {
+    for i in 0..3 {
+        let linear = color[i] / 255f32;
+        if sample_index == 0 {
+            hdr[i] = linear;
+        } else {
+            hdr[i] += (linear - hdr[i]) / (sample_index + 1) as f32;
+        }
+    }
+    hdr[3] = 1f32;
+}
+
+// The averaged normal is made unit length again, like accumulate_aovs of the kernel.
+fn accumulate_aovs(aovs: &mut [f32], sample_index: u32, aov: &[f32; AOV_LEN]) {
+    for i in 0..AOV_LEN - 1 {
+        if sample_index == 0 {
+            aovs[i] = aov[i];
+        } else {
+            aovs[i] += (aov[i] - aovs[i]) / (sample_index + 1) as f32;
+        }
+    }
+    if sample_index == 0 {
+        aovs[AOV_LEN - 1] = aov[AOV_LEN - 1];
+    }
+    let normal = &mut aovs[4..7];
+    let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+    if length > 0f32 {
+        for component in normal.iter_mut() {
+            *component /= length;
+        }
+    }
+}
+
+// Renders the pixels of tile into hdr, which only holds that tile, spreading its rows over threads.
+// The AOVs of the tile go to aovs when it's given.
+fn render_tile_pixels(tile: &Tile, threads: usize, sampling: &FrameSampling, frame_camera: &FrameCamera, frame_scene: &FrameScene, hdr: &mut [f32], aovs: Option<&mut [f32]>) {
+    let tile_width = tile.width as usize;
+    let rows_per_thread = (tile.height as usize).div_ceil(threads).max(1);
+    let chunk_size = rows_per_thread * tile_width * 4;
+    let mut aov_chunks = aovs.into_iter().flat_map(|aovs| aovs.chunks_mut(rows_per_thread * tile_width * AOV_LEN));
+    thread::scope(|s| {
+        for (chunk_index, chunk) in hdr.chunks_mut(chunk_size).enumerate() {
+            let mut aov_chunk = aov_chunks.next();
+            s.spawn(move || {
+                for (i, pixel) in chunk.chunks_mut(4).enumerate() {
+                    let local_id = chunk_index * rows_per_thread * tile_width + i;
+                    let x = tile.x + (local_id % tile_width) as u32;
+                    let y = tile.y + (local_id / tile_width) as u32;
+                    let (color, aov) = render_sample(x, y, sampling, frame_camera, frame_scene);
+                    accumulate_pixel(pixel, sampling.sample_index, &color);
+                    if let Some(aov_chunk) = aov_chunk.as_mut() {
+                        accumulate_aovs(&mut aov_chunk[i * AOV_LEN..(i + 1) * AOV_LEN], sampling.sample_index, &aov);
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn tone_map_pixels(threads: usize, tone_mapping: &ToneMapping, hdr: &[f32], pixels: &mut [u8]) {
+    let chunk_size = (pixels.len() / 4).div_ceil(threads).max(1) * 4;
+    thread::scope(|s| {
+        for (chunk, hdr_chunk) in pixels.chunks_mut(chunk_size).zip(hdr.chunks(chunk_size)) {
+            s.spawn(move || {
+                for (pixel, hdr) in chunk.chunks_mut(4).zip(hdr_chunk.chunks(4)) {
+                    tone_mapping.map_pixel(hdr, pixel);
+                }
+            });
+        }
+    });
+}
+
+pub struct CpuBackend {
+    width: u32,
+    height: u32,
+    threads: usize,
+    output: Vec<u8>,
+    hdr_buffer: Vec<f32>,
+    aovs_enabled: bool,
+    aov_buffer: Vec<f32>,
+    // Frames are rendered right away on submit, these are the ones that weren't picked up yet.
+    finished_frames: VecDeque<(Vec<u8>, FrameStats)>,
+    accumulation: Accumulation,
+    // What the accumulated image shows, a frame that differs from it starts the accumulation over.
+    accumulated_scene: PackedScene,
+    accumulated_camera: Vec<f32>,
+    accumulated_directionlight_direction: Vec<f32>,
+    accumulated_directionlight_color: Vec<u8>,
+    anti_aliasing: AntiAliasing,
+    tone_mapping: ToneMapping,
+    stereo: Option<StereoRig>,
+}
+
+impl CpuBackend {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            threads: 0,
+            output: Vec::new(),
+            hdr_buffer: Vec::new(),
+            aovs_enabled: false,
+            aov_buffer: Vec::new(),
+            finished_frames: VecDeque::new(),
+            accumulation: Accumulation::new(),
+            accumulated_scene: PackedScene::default(),
+            accumulated_camera: Vec::new(),
+            accumulated_directionlight_direction: Vec::new(),
+            accumulated_directionlight_color: Vec::new(),
+            anti_aliasing: AntiAliasing::default(),
+            tone_mapping: ToneMapping::default(),
+            stereo: None,
+         }
+    }
+}
+
+impl RenderBackend for CpuBackend {
+    fn init(&mut self) -> Result<(), RendererError> {
+        self.threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        self.output = Vec::new();
+        self.hdr_buffer = Vec::new();
+        self.aov_buffer = Vec::new();
+        self.finished_frames.clear();
+        self.accumulation.reset();
+        Ok(())
+    }
+
+    fn submit_frame(&mut self, mut camera: Camera, mut render_objects: Vec<RenderObject>, directionlight_direction: Vec<f32>, directionlight_color: Vec<u8>) -> Result<(), RendererError> {
+        if self.threads == 0 {
+            return Err(RendererError::RendererNotInitializedError);
+        }
+        if self.finished_frames.len() >= MAX_FRAMES_IN_FLIGHT {
+            return Err(RendererError::FramesInFlightError);
+        }
+        if self.output.is_empty() {
+            let frame_len = (self.width as usize).checked_mul(self.height as usize)
+                .and_then(|pixels| pixels.checked_mul(4))
+                .ok_or(RendererError::DimensionsTooBigError)?;
+            self.output = vec![0u8; frame_len];
+            self.hdr_buffer = vec![0f32; frame_len];
+            self.accumulation.reset();
+        }
+        if self.aovs_enabled && self.aov_buffer.is_empty() {
+            self.aov_buffer = vec![0f32; (self.width as usize) * (self.height as usize) * AOV_LEN];
+            self.accumulation.reset();
+        }
+        let mut stats = FrameStats::default();
+        // There's no device to upload to, packing the scene is the closest thing to it.
+        let upload_start = Instant::now();
+        let scene = PackedScene::new(&mut render_objects);
+        let (camera_vec, stereo_layout) = stereo_camera_vec(&mut camera, &self.stereo);
+        stats.upload_time = upload_start.elapsed();
+        let scene_changed = scene != self.accumulated_scene
+            || camera_vec != self.accumulated_camera
+            || directionlight_direction != self.accumulated_directionlight_direction
+            || directionlight_color != self.accumulated_directionlight_color;
+        let kernel_start = Instant::now();
+        // A converged image isn't rendered again, but is still tone mapped in case the tone mapping changed.
+        if let Some(sample_index) = self.accumulation.next_sample(scene_changed) {
+            stats.rays_traced = (self.width as u64) * (self.height as u64) * (self.anti_aliasing.samples_per_pixel as u64);
+            if stereo_layout == StereoLayout::ANAGLYPH as u8 {
+                stats.rays_traced *= 2;
+            }
+            stats.objects_uploaded = scene.object_amnt as usize;
+            let frame_camera = FrameCamera::new(&camera_vec, stereo_layout, &mut camera, self.width, self.height);
+            let frame_scene = FrameScene { scene: &scene, directionlight_direction: &directionlight_direction, directionlight_color: &directionlight_color };
+            let sampling = FrameSampling { sample_index, anti_aliasing: &self.anti_aliasing };
+            let frame = Tile::new(0, 0, self.width, self.height);
+            render_tile_pixels(&frame, self.threads, &sampling, &frame_camera, &frame_scene,
+                               &mut self.hdr_buffer, self.aovs_enabled.then_some(&mut self.aov_buffer[..]));
+        }
+        tone_map_pixels(self.threads, &self.tone_mapping, &self.hdr_buffer, &mut self.output);
+        stats.kernel_time = kernel_start.elapsed();
+        if scene_changed {
+            self.accumulated_scene = scene;
+            self.accumulated_camera = camera_vec;
+            self.accumulated_directionlight_direction = directionlight_direction;
+            self.accumulated_directionlight_color = directionlight_color;
+        }
+
+        let readback_start = Instant::now();
+        let frame = self.output.clone();
+        stats.readback_time = readback_start.elapsed();
+        self.finished_frames.push_back((frame, stats));
+        Ok(())
+    }
+
+    fn wait_frame(&mut self) -> Result<Option<(Vec::<u8>, FrameStats)>, RendererError> {
+        Ok(self.finished_frames.pop_front())
+    }
+
+    fn get_frames_in_flight(&self) -> usize {
+        self.finished_frames.len()
+    }
+
+    fn render_tiles(&mut self, mut camera: Camera, mut render_objects: Vec<RenderObject>, directionlight_direction: Vec<f32>, directionlight_color: Vec<u8>, tiles: &[Tile], on_tile: &mut TileCallback) -> Result<(), RendererError> {
+        if self.threads == 0 {
+            return Err(RendererError::RendererNotInitializedError);
+        }
+        let scene = PackedScene::new(&mut render_objects);
+        let (camera_vec, stereo_layout) = stereo_camera_vec(&mut camera, &self.stereo);
+        let frame_camera = FrameCamera::new(&camera_vec, stereo_layout, &mut camera, self.width, self.height);
+        let frame_scene = FrameScene { scene: &scene, directionlight_direction: &directionlight_direction, directionlight_color: &directionlight_color };
+        let sampling = FrameSampling { sample_index: 0, anti_aliasing: &self.anti_aliasing };
+
+        let max_tile_len = tiles.iter().map(|tile| tile.get_pixel_count()).max().unwrap_or(0) * 4;
+        let mut pixels = vec![0u8; max_tile_len];
+        let mut hdr = vec![0f32; max_tile_len];
+        let mut aovs = if self.aovs_enabled { vec![0f32; max_tile_len / 4 * AOV_LEN] } else { Vec::new() };
+        for tile in tiles.iter() {
+            let tile_len = tile.get_pixel_count() * 4;
+            let aov_len = tile.get_pixel_count() * AOV_LEN;
+            render_tile_pixels(tile, self.threads, &sampling, &frame_camera, &frame_scene,
+                               &mut hdr[..tile_len], self.aovs_enabled.then(|| &mut aovs[..aov_len]));
+            tone_map_pixels(self.threads, &self.tone_mapping, &hdr[..tile_len], &mut pixels[..tile_len]);
+            on_tile(tile, &pixels[..tile_len], self.aovs_enabled.then(|| &aovs[..aov_len]));
+        }
+        Ok(())
+    }
+
+    fn resize(&mut self, width: u32, height: u32) -> Result<(), RendererError> {
+        self.width = width;
+        self.height = height;
+        // Reallocated at the new size on the next frame.
+        self.output = Vec::new();
+        self.hdr_buffer = Vec::new();
+        self.aov_buffer = Vec::new();
+        self.finished_frames.clear();
+        self.accumulation.reset();
+        Ok(())
+    }
+
+    fn read_hdr_frame(&mut self) -> Result<Vec::<f32>, RendererError> {
+        if self.hdr_buffer.is_empty() {
+            return Err(RendererError::RendererNotInitializedError);
+        }
+        Ok(self.hdr_buffer.clone())
+    }
+
+    fn read_aov_frame(&mut self) -> Result<Vec::<f32>, RendererError> {
+        if !self.aovs_enabled {
+            return Err(RendererError::AovsDisabledError);
+        }
+        if self.aov_buffer.is_empty() {
+            return Err(RendererError::RendererNotInitializedError);
+        }
+        Ok(self.aov_buffer.clone())
+    }
+
+    fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.tone_mapping = tone_mapping;
+    }
+
+    fn set_accumulation(&mut self, enabled: bool) {
+        self.accumulation.set_enabled(enabled);
+    }
+
+    fn reset_accumulation(&mut self) {
+        self.accumulation.reset();
+    }
+
+    fn get_accumulated_samples(&self) -> u32 {
+        self.accumulation.get_sample_count()
+    }
+
+    fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) {
+        self.anti_aliasing = anti_aliasing;
+        self.accumulation.reset();
+    }
+
+    fn set_stereo(&mut self, stereo: Option<StereoRig>) {
+        self.stereo = stereo;
+        self.accumulation.reset();
+    }
+
+    fn set_aovs(&mut self, enabled: bool) {
+        self.aovs_enabled = enabled;
+        // Allocated on the next frame, which starts the accumulation over as the buffer has no history.
+        self.aov_buffer = Vec::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::cframe::CFrame;
+    use crate::engine::mesh::Mesh;
+    use crate::engine::render::Renderable;
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} is not {}", a, b);
+    }
+
+    // Two triangles sharing the diagonal from (-1, -1) to (1, 1) of a square in the plane z = 0 of the mesh.
+    fn square_mesh_props() -> (Vec<f32>, Vec<u32>) {
+        let mut mesh = Mesh::new(vec![[-1.0, -1.0, 0.0], [1.0, -1.0, 0.0], [1.0, 1.0, 0.0], [-1.0, 1.0, 0.0]], vec![[0, 1, 2], [0, 2, 3]]).unwrap();
+        let mut render_object = mesh.get_render_object();
+        (render_object.get_object_props_vec(), render_object.get_object_uint_props_vec())
+    }
+
+    #[test]
+    fn sphere_is_hit_where_the_ray_enters_it() {
+        let sphere = CFrame::new_from_pos(0.0, 0.0, -10.0).to_vec();
+        assert_near(intersect_sphere(&sphere, 2.0, &ray_cframe(&[0.0; 3], &[0.0, 0.0, -1.0])), 8.0);
+        // From inside it's where the ray leaves it.
+        assert_near(intersect_sphere(&sphere, 2.0, &ray_cframe(&[0.0, 0.0, -10.0], &[0.0, 0.0, -1.0])), 2.0);
+        assert_eq!(intersect_sphere(&sphere, 2.0, &ray_cframe(&[0.0; 3], &[0.0, 0.0, 1.0])), -1.0);
+        assert_eq!(intersect_sphere(&sphere, 2.0, &ray_cframe(&[3.0, 0.0, 0.0], &[0.0, 0.0, -1.0])), -1.0);
+    }
+
+    #[test]
+    fn plane_is_only_hit_in_front_of_the_ray() {
+        let plane = CFrame::new_from_pos(0.0, -5.0, 0.0).to_vec();
+        assert_near(intersect_plane(&plane, &ray_cframe(&[0.0; 3], &[0.0, -1.0, 0.0])), 5.0);
+        assert_near(intersect_plane(&plane, &ray_cframe(&[0.0; 3], &[0.6, -0.8, 0.0])), 6.25);
+        assert_eq!(intersect_plane(&plane, &ray_cframe(&[0.0; 3], &[0.0, 1.0, 0.0])), -1.0);
+        assert_eq!(intersect_plane(&plane, &ray_cframe(&[0.0; 3], &[1.0, 0.0, 0.0])), -1.0);
+    }
+
+    #[test]
+    fn cuboid_is_hit_on_the_face_facing_the_ray() {
+        let mut cframe = CFrame::new_from_pos(0.0, 0.0, -10.0);
+        let half_size = [1.0, 2.0, 3.0];
+        let cuboid = cframe.to_vec();
+        assert_near(intersect_cuboid(&cuboid, &half_size, &ray_cframe(&[0.0; 3], &[0.0, 0.0, -1.0])), 7.0);
+        assert_eq!(cuboid_normal(&cuboid, &half_size, &[0.0, 0.0, -7.0]), [0.0, 0.0, 1.0]);
+        assert_eq!(cuboid_normal(&cuboid, &half_size, &[0.5, -2.0, -9.0]), [0.0, -1.0, 0.0]);
+        // From inside it's where the ray leaves it.
+        assert_near(intersect_cuboid(&cuboid, &half_size, &ray_cframe(&[0.0, 0.0, -10.0], &[1.0, 0.0, 0.0])), 1.0);
+        assert_eq!(intersect_cuboid(&cuboid, &half_size, &ray_cframe(&[1.5, 0.0, 0.0], &[0.0, 0.0, -1.0])), -1.0);
+        // A quarter turn puts the width of the box along the ray.
+        cframe.multiply_angles(0.0, std::f32::consts::FRAC_PI_2, 0.0);
+        assert_near(intersect_cuboid(&cframe.to_vec(), &half_size, &ray_cframe(&[0.0; 3], &[0.0, 0.0, -1.0])), 9.0);
+    }
+
+    #[test]
+    fn mesh_is_hit_on_the_side_facing_the_ray() {
+        let cframe = CFrame::new_from_pos(0.0, 0.0, -5.0).to_vec();
+        let (props, uint_props) = square_mesh_props();
+        let ray = ray_cframe(&[0.5, -0.5, 0.0], &[0.0, 0.0, -1.0]);
+        let (triangle, t, weights) = trace_mesh(&cframe, &props, &uint_props, &ray).unwrap();
+        assert_eq!(triangle, 0);
+        assert_near(t, 5.0);
+        assert_near(weights[0] + weights[1] + weights[2], 1.0);
+        assert_eq!(mesh_normal(&cframe, &props, &uint_props, &ray, triangle, &weights), [0.0, 0.0, 1.0]);
+
+        let ray = ray_cframe(&[-0.5, 0.5, -10.0], &[0.0, 0.0, 1.0]);
+        let (triangle, t, weights) = trace_mesh(&cframe, &props, &uint_props, &ray).unwrap();
+        assert_eq!(triangle, 1);
+        assert_near(t, 5.0);
+        assert_eq!(mesh_normal(&cframe, &props, &uint_props, &ray, triangle, &weights), [0.0, 0.0, -1.0]);
+
+        assert!(trace_mesh(&cframe, &props, &uint_props, &ray_cframe(&[1.5, 0.0, 0.0], &[0.0, 0.0, -1.0])).is_none());
+        assert!(trace_mesh(&cframe, &props, &uint_props, &ray_cframe(&[0.5, -0.5, 0.0], &[0.0, 0.0, 1.0])).is_none());
+    }
+
+    #[test]
+    fn mesh_has_no_holes_along_a_shared_edge() {
+        let cframe = CFrame::new_from_pos(0.0, 0.0, -5.0).to_vec();
+        let (props, uint_props) = square_mesh_props();
+        for direction in [[0.0, 0.0, -1.0], [0.3, -0.2, -1.0], [-0.7, 0.1, -1.0]] {
+            for step in 1..100 {
+                let along_edge = -1.0 + step as f32 * 0.02;
+                let origin = [along_edge - direction[0] * 5.0, along_edge - direction[1] * 5.0, 0.0];
+                let hit = trace_mesh(&cframe, &props, &uint_props, &ray_cframe(&origin, &direction));
+                assert!(hit.is_some(), "ray {:?} along {:?} went through the edge", origin, direction);
+            }
+        }
+    }
+
+    #[test]
+    fn object_without_a_color_renders_white() {
+        let colored = RenderObject::new(CFrame::new_from_pos(5.0, 0.0, -10.0), RenderType::SPHERE, vec![1.0], vec![255, 0, 0]);
+        let uncolored = RenderObject::new(CFrame::new_from_pos(0.0, 0.0, -10.0), RenderType::SPHERE, vec![2.0], Vec::new());
+        let scene = PackedScene::new(&mut [colored, uncolored]);
+        let (pixel, aov) = render_pixel(&scene, &ray_cframe(&[0.0; 3], &[0.0, 0.0, -1.0]), 0.0, &[0.0, 0.0, -1.0], &[255, 255, 255]);
+        assert_eq!(aov[AOV_LEN - 1], 1.0);
+        assert_eq!(&aov[7..10], &[1.0, 1.0, 1.0]);
+        assert!(pixel[0] > 0.0 && pixel[0] == pixel[1] && pixel[1] == pixel[2], "{:?} is not white", pixel);
+    }
+}
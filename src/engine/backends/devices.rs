@@ -0,0 +1,139 @@
+use std::fmt;
+use ocl::{Platform, Device};
+use ocl::enums::{DeviceInfo, DeviceInfoResult};
+use crate::engine::error::RendererError;
+
+pub struct DeviceDescription {
+    pub platform_index: usize,
+    pub platform_name: String,
+    pub device_index: usize,
+    pub name: String,
+    pub vendor: String,
+    pub device_type: String,
+    pub version: String,
+    pub compute_units: u32,
+    pub max_clock_mhz: u32,
+    pub global_mem_bytes: u64,
+    pub max_work_group_size: usize,
+}
+
+impl DeviceDescription {
+    fn new(platform_index: usize, platform: &Platform, device_index: usize, device: &Device) -> Result<Self, RendererError> {
+        let mut description = Self {
+            platform_index,
+            platform_name: platform.name().map_err(RendererError::DeviceQueryError)?,
+            device_index,
+            name: device.name().map_err(RendererError::DeviceQueryError)?,
+            vendor: device.vendor().map_err(RendererError::DeviceQueryError)?,
+            device_type: String::new(),
+            version: String::new(),
+            compute_units: 0,
+            max_clock_mhz: 0,
+            global_mem_bytes: 0,
+            max_work_group_size: device.max_wg_size().map_err(RendererError::DeviceQueryError)?,
+        };
+        let infos = [DeviceInfo::Type, DeviceInfo::Version, DeviceInfo::MaxComputeUnits, DeviceInfo::MaxClockFrequency, DeviceInfo::GlobalMemSize];
+        for info in infos {
+            match device.info(info).map_err(RendererError::DeviceQueryError)? {
+                DeviceInfoResult::Type(device_type) => description.device_type = format!("{:?}", device_type),
+                DeviceInfoResult::Version(version) => description.version = version.to_string(),
+                DeviceInfoResult::MaxComputeUnits(units) => description.compute_units = units,
+                DeviceInfoResult::MaxClockFrequency(mhz) => description.max_clock_mhz = mhz,
+                DeviceInfoResult::GlobalMemSize(bytes) => description.global_mem_bytes = bytes,
+                _ => (),
+            }
+        }
+        Ok(description)
+    }
+}
+
+impl fmt::Display for DeviceDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {} ({}) - {}, OpenCL {}, {} compute units @ {} MHz, {} MiB global memory, max work group size {}",
+               self.device_index, self.name, self.vendor, self.device_type, self.version, self.compute_units, self.max_clock_mhz,
+               self.global_mem_bytes / (1024 * 1024), self.max_work_group_size)
+    }
+}
+
+// Platform::list panics when no OpenCL driver is installed, this reports it as an error instead.
+fn list_platforms() -> Result<Vec<Platform>, RendererError> {
+    let platform_ids = ocl::core::get_platform_ids().map_err(|e| RendererError::DeviceQueryError(e.into()))?;
+    Ok(platform_ids.into_iter().map(Platform::new).collect())
+}
+
+// Lists every device of every OpenCL platform on this machine.
+pub fn list_devices() -> Result<Vec<DeviceDescription>, RendererError> {
+    let mut descriptions = Vec::new();
+    for (platform_index, platform) in list_platforms()?.iter().enumerate() {
+        let devices = Device::list_all(platform).map_err(RendererError::DeviceQueryError)?;
+        for (device_index, device) in devices.iter().enumerate() {
+            descriptions.push(DeviceDescription::new(platform_index, platform, device_index, device)?);
+        }
+    }
+    Ok(descriptions)
+}
+
+// A selector for every device of every platform, to render on all of them at once.
+pub fn select_all_devices() -> Result<Vec<DeviceSelector>, RendererError> {
+    Ok(list_devices()?.iter().map(|description| DeviceSelector {
+        platform: Some(description.platform_index.to_string()),
+        device: Some(description.device_index.to_string()),
+    }).collect())
+}
+
+// Picks a platform and device by index or by a case insensitive substring of their name.
+// Leaving both empty keeps whatever OCL chooses by default.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceSelector {
+    pub platform: Option<String>,
+    pub device: Option<String>,
+}
+
+fn matches(query: &str, index: usize, name: &str) -> bool {
+    match query.parse::<usize>() {
+        Ok(query_index) => query_index == index,
+        Err(_) => name.to_lowercase().contains(&query.to_lowercase()),
+    }
+}
+
+impl DeviceSelector {
+    pub fn is_default(&self) -> bool {
+        self.platform.is_none() && self.device.is_none()
+    }
+
+    pub fn select(&self) -> Result<(Platform, Device), RendererError> {
+        let platforms = list_platforms()?;
+        let candidates: Vec<(usize, &Platform)> = match self.platform.as_ref() {
+            Some(query) => {
+                let found: Vec<(usize, &Platform)> = platforms.iter().enumerate()
+                    .filter(|(index, platform)| matches(query, *index, &platform.name().unwrap_or_default()))
+                    .collect();
+                if found.is_empty() {
+                    return Err(RendererError::PlatformNotFoundError(query.clone()));
+                }
+                found
+            }
+            None => platforms.iter().enumerate().collect(),
+        };
+
+        for (candidate_index, (_, platform)) in candidates.iter().enumerate() {
+            let devices = Device::list_all(*platform).map_err(RendererError::DeviceQueryError)?;
+            let device = match self.device.as_ref() {
+                Some(query) => {
+                    // An index only ever refers to the first matching platform, a name is searched on all of them.
+                    if query.parse::<usize>().is_ok() && candidate_index > 0 {
+                        break;
+                    }
+                    devices.iter().enumerate()
+                        .find(|(index, device)| matches(query, *index, &device.name().unwrap_or_default()))
+                        .map(|(_, device)| *device)
+                }
+                None => devices.first().copied(),
+            };
+            if let Some(device) = device {
+                return Ok((**platform, device));
+            }
+        }
+        Err(RendererError::DeviceNotFoundError(self.device.clone().unwrap_or(String::from("any"))))
+    }
+}
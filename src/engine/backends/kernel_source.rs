@@ -0,0 +1,143 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use crate::engine::error::RendererError;
+
+// The program is built from this file, every other file is pulled in through #include "file.cl".
+pub const KERNEL_ENTRY: &str = "render.cl";
+
+// Directory the kernels are embedded from, which is where to edit them during development.
+pub const KERNEL_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/engine/backends/kernels");
+
+const EMBEDDED_KERNELS: [(&str, &str); 8] = [
+    ("render.cl", include_str!("kernels/render.cl")),
+    ("shading.cl", include_str!("kernels/shading.cl")),
+    ("intersect.cl", include_str!("kernels/intersect.cl")),
+    ("math.cl", include_str!("kernels/math.cl")),
+    ("mesh.cl", include_str!("kernels/mesh.cl")),
+    ("sampling.cl", include_str!("kernels/sampling.cl")),
+    ("camera.cl", include_str!("kernels/camera.cl")),
+    ("tonemap.cl", include_str!("kernels/tonemap.cl")),
+];
+
+#[derive(Clone, Debug, Default)]
+pub enum KernelSource {
+    // The .cl files as they were when the binary was built.
+    #[default]
+    EMBEDDED,
+    // The .cl files in this directory, which are watched and rebuilt whenever one of them changes.
+    DIRECTORY(PathBuf),
+}
+
+impl KernelSource {
+    fn read(&self, name: &str) -> Result<String, RendererError> {
+        match self {
+            KernelSource::EMBEDDED => {
+                EMBEDDED_KERNELS.iter()
+                    .find(|(file_name, _)| *file_name == name)
+                    .map(|(_, source)| source.to_string())
+                    .ok_or(RendererError::KernelNotFoundError(name.to_string()))
+            }
+            KernelSource::DIRECTORY(dir) => {
+                fs::read_to_string(dir.join(name)).map_err(|e| RendererError::KernelReadError(name.to_string(), e))
+            }
+        }
+    }
+
+    // Path of every file that went into the last load, only known for sources on disk.
+    fn paths(&self, names: &[String]) -> Vec<PathBuf> {
+        match self {
+            KernelSource::EMBEDDED => Vec::new(),
+            KernelSource::DIRECTORY(dir) => names.iter().map(|name| dir.join(name)).collect(),
+        }
+    }
+
+    // Source of the whole program with every #include replaced by the file it names.
+    // Like #pragma once, a file that was already included is skipped, which also breaks include cycles.
+    pub fn load(&self) -> Result<(String, Vec<PathBuf>), RendererError> {
+        let mut included = Vec::new();
+        let mut source = String::new();
+        self.append_file(KERNEL_ENTRY, &mut included, &mut source)?;
+        Ok((source, self.paths(&included)))
+    }
+
+    fn append_file(&self, name: &str, included: &mut Vec<String>, source: &mut String) -> Result<(), RendererError> {
+        if included.iter().any(|file_name| file_name == name) {
+            return Ok(());
+        }
+        included.push(name.to_string());
+        for line in self.read(name)?.lines() {
+            let include = line.trim().strip_prefix("#include")
+                .map(|rest| rest.trim().trim_matches('"'));
+            match include {
+                Some(include) => self.append_file(include, included, source)?,
+                None => {
+                    source.push_str(line);
+                    source.push('\n');
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Remembers when the kernel files were last modified, to notice when one of them is saved.
+pub struct KernelWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl KernelWatcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        Self {
+            files: paths.into_iter().map(|path| {
+                let time = modified(&path);
+                (path, time)
+            }).collect(),
+         }
+    }
+
+    pub fn has_changed(&self) -> bool {
+        self.files.iter().any(|(path, time)| modified(path) != *time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_files_are_included_once() {
+        let (source, paths) = KernelSource::EMBEDDED.load().unwrap();
+        assert!(paths.is_empty());
+        assert!(source.lines().all(|line| !line.trim().starts_with("#include")));
+        // math.cl is included by both intersect.cl and mesh.cl.
+        for (name, file) in EMBEDDED_KERNELS.iter() {
+            let first_line = file.lines().find(|line| !line.trim().is_empty() && !line.trim().starts_with("#include")).unwrap();
+            assert_eq!(source.matches(first_line).count(), 1, "{} is not included exactly once", name);
+        }
+    }
+
+    #[test]
+    fn include_cycles_are_broken() {
+        let dir = std::env::temp_dir().join(format!("kernel_source_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(KERNEL_ENTRY), "#include \"a.cl\"\n#include \"b.cl\"\nentry\n").unwrap();
+        fs::write(dir.join("a.cl"), "#include \"b.cl\"\na\n").unwrap();
+        fs::write(dir.join("b.cl"), "  #include \"render.cl\"\n#include \"a.cl\"\nb\n").unwrap();
+        let source = KernelSource::DIRECTORY(dir.clone()).load();
+        fs::remove_dir_all(&dir).unwrap();
+        let (source, paths) = source.unwrap();
+        assert_eq!(source, "b\na\nentry\n");
+        assert_eq!(paths, vec![dir.join(KERNEL_ENTRY), dir.join("a.cl"), dir.join("b.cl")]);
+    }
+
+    #[test]
+    fn missing_files_are_reported() {
+        let dir = std::env::temp_dir().join(format!("kernel_source_missing_test_{}", std::process::id()));
+        assert!(matches!(KernelSource::DIRECTORY(dir).load(), Err(RendererError::KernelReadError(..))));
+    }
+}
@@ -0,0 +1,114 @@
+// Primary rays, reading the camera as laid out by Camera::to_vec: the cframe, fov, projection,
+// aperture radius, focus distance, aperture blades, lens shift and the cframe at the end of the shutter interval.
+#include "sampling.cl"
+
+// Floats per camera, CAMERA_VEC_LEN in camera.rs.
+#define CAMERA_VEC_LEN 30
+
+// Origin and direction relative to the camera of the ray through the point (px, py) of the image, before it
+// goes through the lens. Returns false when the point isn't part of the image, like the corners of a fisheye.
+bool primary_ray(float px,
+                 float py,
+                 uint width,
+                 uint height,
+                 __constant float *camera,
+                 float camera_width,
+                 float camera_height,
+                 float focal_length,
+                 float *origin,
+                 float *direction)
+{
+    // For the panoramic projections the image spans angles instead of a plane.
+    float cam_x = - (camera_width / 2) + ((px / (float) width) * camera_width);
+    float cam_y = - (camera_height / 2) + ((py / (float) height) * camera_height);
+    origin[0] = 0.0f;
+    origin[1] = 0.0f;
+    origin[2] = 0.0f;
+    // Image rows go down, the y axis of the camera goes up.
+    direction[0] = cam_x + camera[17] * focal_length;
+    direction[1] = -cam_y;
+    direction[2] = -focal_length;
+    uchar projection = (uchar) camera[13];
+    if (projection == 1) {
+        // Orthographic rays run parallel, each starting from its own point of the image plane.
+        origin[0] = cam_x;
+        origin[1] = -cam_y;
+        direction[0] = 0.0f;
+        direction[1] = 0.0f;
+        direction[2] = -1.0f;
+    } else if (projection == 2) {
+        // Equirectangular, cam_x is the longitude and -cam_y the latitude.
+        direction[0] = cos(-cam_y) * sin(cam_x);
+        direction[1] = sin(-cam_y);
+        direction[2] = -cos(-cam_y) * cos(cam_x);
+    } else if (projection == 3 || projection == 4) {
+        // Fisheye, the distance from the centre of the image is the angle from the viewing direction.
+        float half_fov = radians(camera[12]) / 2.0f;
+        float r = sqrt(cam_x * cam_x + cam_y * cam_y);
+        if (r > half_fov) {
+            return false;
+        }
+        float theta = r;
+        if (projection == 4) {
+            // Equisolid angle, which keeps the area of everything the same instead of the angles.
+            theta = 2.0f * asin(r / half_fov * sin(half_fov / 2.0f));
+        }
+        direction[0] = 0.0f;
+        direction[1] = 0.0f;
+        direction[2] = -1.0f;
+        if (r > 0.0f) {
+            direction[0] = sin(theta) * cam_x / r;
+            direction[1] = sin(theta) * -cam_y / r;
+            direction[2] = -cos(theta);
+        }
+    }
+    return true;
+}
+
+// Uniform point on a lens of radius 1, a disk or a regular polygon when it has at least 3 blades.
+void sample_lens(uint blades,
+                 uint *rng_state,
+                 float *lens_x,
+                 float *lens_y)
+{
+    float u = random_float(rng_state);
+    float v = random_float(rng_state);
+    if (blades < 3) {
+        float r = sqrt(u);
+        float phi = 2.0f * M_PI_F * v;
+        *lens_x = r * cos(phi);
+        *lens_y = r * sin(phi);
+        return;
+    }
+    // Pick one of the triangles between the centre and two neighbouring corners, then a point inside it.
+    float segment = floor(u * (float) blades);
+    float r = sqrt(u * (float) blades - segment);
+    float angle0 = 2.0f * M_PI_F * segment / (float) blades;
+    float angle1 = 2.0f * M_PI_F * (segment + 1.0f) / (float) blades;
+    *lens_x = r * ((1.0f - v) * cos(angle0) + v * cos(angle1));
+    *lens_y = r * ((1.0f - v) * sin(angle0) + v * sin(angle1));
+}
+
+// Thin lens: moves the start of the ray to a random point of the aperture and aims it at the point where
+// the primary ray crosses the focus plane, so only things at the focus distance stay sharp.
+// Panoramic projections have no focus plane and are always sharp.
+void apply_lens(__constant float *camera,
+                uint *rng_state,
+                float *origin,
+                float *direction)
+{
+    float aperture_radius = camera[14];
+    if (aperture_radius <= 0.0f || (uchar) camera[13] >= 2) {
+        return;
+    }
+    float focus_distance = camera[15];
+    float lens_x, lens_y;
+    sample_lens((uint) camera[16], rng_state, &lens_x, &lens_y);
+    float t = focus_distance / -direction[2];
+    float focus_point[3] = { origin[0] + direction[0] * t, origin[1] + direction[1] * t, origin[2] + direction[2] * t };
+    origin[0] += lens_x * aperture_radius;
+    origin[1] += lens_y * aperture_radius;
+    direction[0] = focus_point[0] - origin[0];
+    direction[1] = focus_point[1] - origin[1];
+    direction[2] = focus_point[2] - origin[2];
+}
@@ -0,0 +1,263 @@
+#include "math.cl"
+#include "mesh.cl"
+
+bool solveQuadratic(const float a, const float b, const float c, 
+					    float *x0, float *x1) {
+    float discr = b * b - 4 * a * c;
+    if (discr < 0) return false;
+    else if (discr == 0) *x0 = *x1 = -0.5 * b / a;
+    else {
+        float q = (b > 0) ?
+            -0.5 * (b + sqrt(discr)) :
+            -0.5 * (b - sqrt(discr));
+        *x0 = q / a;
+        *x1 = c / q;
+    }
+    // if (x0 > x1) std::swap(x0, x1);
+    
+    return true;
+}
+
+void intersect_sphere(float *sphere_cframe,
+                      float sphere_radius,
+                      float *ray_cframe,
+                      float *t)
+{
+    float Lx = sphere_cframe[0] - ray_cframe[0];
+    float Ly = sphere_cframe[1] - ray_cframe[1];
+    float Lz = sphere_cframe[2] - ray_cframe[2];
+
+    float a = ray_cframe[5] * ray_cframe[5] + ray_cframe[8] * ray_cframe[8] + ray_cframe[11] * ray_cframe[11];
+    float b = 2 * (ray_cframe[5] * Lx + ray_cframe[8] * Ly + ray_cframe[11] * Lz);
+    float c = Lx * Lx + Ly * Ly + Lz * Lz - sphere_radius * sphere_radius;
+    float t0, t1;
+    if (solveQuadratic(a, b, c, &t0, &t1)) {
+        if (t0 > 0 && t1 > 0){
+            *t = min(t0, t1);
+        }
+        else if (t0 > 0) {
+            *t = t0;
+        }
+        else if (t1 > 0) {
+            *t = t1;
+        }
+        else {
+            *t = -1;
+        }
+    } else {
+        *t = -1;
+    }
+}
+
+// Infinite plane through the position of plane_cframe, facing along its up vector, which is the second row.
+void intersect_plane(float *plane_cframe,
+                     float *ray_cframe,
+                     float *t)
+{
+    float denom = -(plane_cframe[6] * ray_cframe[5] + plane_cframe[7] * ray_cframe[8] + plane_cframe[8] * ray_cframe[11]);
+    *t = -1;
+    // Rays running along the plane never hit it.
+    if (fabs(denom) < 1e-6f) {
+        return;
+    }
+    float distance = plane_cframe[6] * (plane_cframe[0] - ray_cframe[0]) + plane_cframe[7] * (plane_cframe[1] - ray_cframe[1]) + plane_cframe[8] * (plane_cframe[2] - ray_cframe[2]);
+    float local_t = distance / denom;
+    if (local_t > 0) {
+        *t = local_t;
+    }
+}
+
+// Box with the given half_size along the axes of its cframe, which are its rows. Slab test in the space of the box:
+// the ray is inside between where it enters the last slab and leaves the first one.
+void intersect_cuboid(float *cuboid_cframe,
+                      __global const float *half_size,
+                      float *ray_cframe,
+                      float *t)
+{
+    float t_near = -INFINITY;
+    float t_far = INFINITY;
+    *t = -1;
+    for (int axis = 0; axis < 3; axis++) {
+        float *row = &cuboid_cframe[3 + axis * 3];
+        float origin = (ray_cframe[0] - cuboid_cframe[0]) * row[0] + (ray_cframe[1] - cuboid_cframe[1]) * row[1] + (ray_cframe[2] - cuboid_cframe[2]) * row[2];
+        float direction = -(ray_cframe[5] * row[0] + ray_cframe[8] * row[1] + ray_cframe[11] * row[2]);
+        if (fabs(direction) < 1e-8f) {
+            // Parallel to the slab, it either always or never is between its sides.
+            if (fabs(origin) > half_size[axis]) {
+                return;
+            }
+            continue;
+        }
+        float t0 = (-half_size[axis] - origin) / direction;
+        float t1 = (half_size[axis] - origin) / direction;
+        t_near = fmax(t_near, fmin(t0, t1));
+        t_far = fmin(t_far, fmax(t0, t1));
+    }
+    if (t_near > t_far || t_far <= 0) {
+        return;
+    }
+    // A ray starting inside the box hits it where it leaves.
+    *t = t_near > 0 ? t_near : t_far;
+}
+
+// Normal of the face of the box the point edge_pos lies on, the axis it's furthest out on relative to the size.
+void cuboid_normal(float *cuboid_cframe,
+                   __global const float *half_size,
+                   float *edge_pos,
+                   float *out_normal)
+{
+    int face_axis = 0;
+    float face_side = 1.0f;
+    float furthest = -1.0f;
+    for (int axis = 0; axis < 3; axis++) {
+        float *row = &cuboid_cframe[3 + axis * 3];
+        float local = (edge_pos[0] - cuboid_cframe[0]) * row[0] + (edge_pos[1] - cuboid_cframe[1]) * row[1] + (edge_pos[2] - cuboid_cframe[2]) * row[2];
+        float relative = fabs(local) / half_size[axis];
+        if (relative > furthest) {
+            furthest = relative;
+            face_axis = axis;
+            face_side = local < 0 ? -1.0f : 1.0f;
+        }
+    }
+    out_normal[0] = cuboid_cframe[3 + face_axis * 3] * face_side;
+    out_normal[1] = cuboid_cframe[4 + face_axis * 3] * face_side;
+    out_normal[2] = cuboid_cframe[5 + face_axis * 3] * face_side;
+}
+
+// RenderType of the objects, see render.rs.
+#define RENDER_TYPE_SPHERE 0
+#define RENDER_TYPE_PLANE 1
+#define RENDER_TYPE_CUBOID 2
+#define RENDER_TYPE_MESH 3
+
+// Hit of the ray with an object of the given type at cframe, props and uint_props are the parameters of the object.
+// out_primitive is the triangle of a mesh that was hit and out_weights the barycentric weights of its vertices,
+// types made of a single surface always give -1.
+void intersect_object(uchar type,
+                      float *cframe,
+                      __global const float *props,
+                      __global const uint *uint_props,
+                      float *ray_cframe,
+                      float *t,
+                      int *out_primitive,
+                      float *out_weights)
+{
+    *out_primitive = -1;
+    switch (type) {
+        case RENDER_TYPE_PLANE:
+            intersect_plane(cframe, ray_cframe, t);
+            break;
+        case RENDER_TYPE_SPHERE:
+            intersect_sphere(cframe, props[0], ray_cframe, t);
+            break;
+        case RENDER_TYPE_CUBOID:
+            intersect_cuboid(cframe, props, ray_cframe, t);
+            break;
+        case RENDER_TYPE_MESH:
+            *out_primitive = trace_mesh(cframe, props, uint_props, ray_cframe, t, out_weights);
+            if (*out_primitive < 0) {
+                *t = -1;
+            }
+            break;
+        default:
+            *t = -1;
+            break;
+    }
+}
+
+// Normal of an object of the given type at cframe, at the point edge_pos where ray_cframe hits its surface, on the
+// primitive intersect_object found. Only called for types intersect_object can hit.
+void object_normal(uchar type,
+                   float *cframe,
+                   __global const float *props,
+                   __global const uint *uint_props,
+                   float *ray_cframe,
+                   float *edge_pos,
+                   int primitive,
+                   float *weights,
+                   float *out_normal)
+{
+    switch (type) {
+        case RENDER_TYPE_PLANE:
+            out_normal[0] = cframe[6];
+            out_normal[1] = cframe[7];
+            out_normal[2] = cframe[8];
+            break;
+        case RENDER_TYPE_CUBOID:
+            cuboid_normal(cframe, props, edge_pos, out_normal);
+            break;
+        case RENDER_TYPE_MESH:
+            mesh_normal(cframe, props, uint_props, ray_cframe, primitive, weights, out_normal);
+            break;
+        case RENDER_TYPE_SPHERE: {
+            float normal[3] = { edge_pos[0] - cframe[0], edge_pos[1] - cframe[1], edge_pos[2] - cframe[2] };
+            float normal_size = sqrt((normal[0] * normal[0]) + (normal[1] * normal[1]) + (normal[2] * normal[2]));
+            out_normal[0] = normal[0] / normal_size;
+            out_normal[1] = normal[1] / normal_size;
+            out_normal[2] = normal[2] / normal_size;
+            break;
+        }
+    }
+}
+
+// Objects are hit where they are at time, see interpolate_cframe. object_type holds the RenderType of every object,
+// the parameters of object i start at object_props[prop_offset[i]] and object_uint_props[uint_prop_offset[i]].
+// out_primitive and out_weights are those of the nearest hit, see intersect_object.
+int intersect_objects(__constant float* object_cframe,
+                      __constant float* object_end_cframe,
+                      __constant uchar *object_type,
+                      unsigned int object_amnt,
+                      float *ray_cframe,
+                      float time,
+                      __global const float *object_props,
+                      __constant uint *prop_offset,
+                      __global const uint *object_uint_props,
+                      __constant uint *uint_prop_offset,
+                      float *out_t,
+                      int *out_primitive,
+                      float *out_weights)
+{
+    float t = 9999999;
+    int index_found = -1;
+    *out_primitive = -1;
+    for (int i = 0; i < object_amnt; i++)
+    {
+        float local_t;
+        int primitive;
+        float weights[3];
+        float cframe[12];
+        interpolate_cframe(&object_cframe[i * 12], &object_end_cframe[i * 12], time, cframe);
+        intersect_object(object_type[i], cframe, &object_props[prop_offset[i]], &object_uint_props[uint_prop_offset[i]], ray_cframe, &local_t, &primitive, weights);
+        if (local_t > 0 && local_t < t) {
+            t = local_t;
+            index_found = i;
+            *out_primitive = primitive;
+            out_weights[0] = weights[0];
+            out_weights[1] = weights[1];
+            out_weights[2] = weights[2];
+        }
+    }
+    *out_t = t;
+    return index_found;
+}
+
+void calculate_normal_vector(__constant float* object_cframe,
+                             __constant float* object_end_cframe,
+                             __constant uchar *object_type,
+                             int object_index,
+                             float time,
+                             __global const float *object_props,
+                             __constant uint *prop_offset,
+                             __global const uint *object_uint_props,
+                             __constant uint *uint_prop_offset,
+                             float *ray_cframe,
+                             float *edge_pos,
+                             int primitive,
+                             float *weights,
+                             float *out_normal)
+{
+    float cframe[12];
+    interpolate_cframe(&object_cframe[object_index * 12], &object_end_cframe[object_index * 12], time, cframe);
+    object_normal(object_type[object_index], cframe, &object_props[prop_offset[object_index]], &object_uint_props[uint_prop_offset[object_index]],
+                  ray_cframe, edge_pos, primitive, weights, out_normal);
+}
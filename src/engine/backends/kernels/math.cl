@@ -0,0 +1,59 @@
+// Matrix helpers for the 3x4 CFrames (position followed by a row major 3x3 rotation) of the camera and objects.
+
+void cframe_multiply_vector(__constant float *cframe,
+                            __private float *pos,
+                            __private float *out)
+{
+    out[0] = cframe[3] * pos[0] + cframe[4] * pos[1] + cframe[5] * pos[2] + cframe[0];
+    out[1] = cframe[6] * pos[0] + cframe[7] * pos[1] + cframe[8] * pos[2] + cframe[1];
+    out[2] = cframe[9] * pos[0] + cframe[10] * pos[1] + cframe[11] * pos[2] + cframe[2];
+}
+
+void normalize_vector(float *vector)
+{
+    float length = sqrt(vector[0] * vector[0] + vector[1] * vector[1] + vector[2] * vector[2]);
+    vector[0] /= length;
+    vector[1] /= length;
+    vector[2] /= length;
+}
+
+// CFrame at time, between 0 for start and 1 for end. The position moves in a straight line and the rotation
+// is blended and made orthonormal again, which is close enough for the small turns made while the shutter is open.
+void interpolate_cframe(__constant float *start,
+                        __constant float *end,
+                        float time,
+                        __private float *out)
+{
+    for (int i = 0; i < 12; i++) {
+        out[i] = start[i] + (end[i] - start[i]) * time;
+    }
+    float *row0 = &out[3];
+    float *row1 = &out[6];
+    normalize_vector(row0);
+    float projection = row1[0] * row0[0] + row1[1] * row0[1] + row1[2] * row0[2];
+    row1[0] -= projection * row0[0];
+    row1[1] -= projection * row0[1];
+    row1[2] -= projection * row0[2];
+    normalize_vector(row1);
+    out[9] = row0[1] * row1[2] - row0[2] * row1[1];
+    out[10] = row0[2] * row1[0] - row0[0] * row1[2];
+    out[11] = row0[0] * row1[1] - row0[1] * row1[0];
+}
+
+// Ray cframe for a ray starting at origin and going along direction, both given relative to the camera,
+// which looks down -z with x to the right and y up. The last column of a ray cframe holds the reversed
+// direction, the rest of its rotation is unused.
+void camera_ray_cframe(__private float *camera,
+                       __private float *origin,
+                       __private float *direction,
+                       __private float *out)
+{
+    float length = sqrt(direction[0] * direction[0] + direction[1] * direction[1] + direction[2] * direction[2]);
+    for (int i = 0; i < 3; i++) {
+        out[i] = camera[i] + origin[0] * camera[3 + i] + origin[1] * camera[6 + i] + origin[2] * camera[9 + i];
+        float world_direction = direction[0] * camera[3 + i] + direction[1] * camera[6 + i] + direction[2] * camera[9 + i];
+        out[3 + i * 3] = 0.0f;
+        out[4 + i * 3] = 0.0f;
+        out[5 + i * 3] = -world_direction / length;
+    }
+}
@@ -0,0 +1,216 @@
+// Triangle meshes, stored in the parameters of the object as laid out by Mesh::get_render_object. The float parameters
+// hold the bounds of the vertices (minimum then maximum corner), the vertex positions, the vertex normals when
+// MESH_HAS_NORMALS is set and the vertex UVs when MESH_HAS_UVS is set. The uint parameters hold the triangle count,
+// the vertex count and the flags, followed by three vertex indices per triangle. Vertices are relative to the cframe
+// of the mesh.
+#include "math.cl"
+
+#define MESH_HEADER_LEN 3
+#define MESH_BOUNDS_LEN 6
+#define MESH_HAS_NORMALS 1
+#define MESH_HAS_UVS 2
+
+// Origin and direction of the ray in the space of the object at cframe, whose rows are its axes.
+void ray_to_local(float *cframe,
+                  float *ray_cframe,
+                  float *origin,
+                  float *direction)
+{
+    for (int axis = 0; axis < 3; axis++) {
+        float *row = &cframe[3 + axis * 3];
+        origin[axis] = (ray_cframe[0] - cframe[0]) * row[0] + (ray_cframe[1] - cframe[1]) * row[1] + (ray_cframe[2] - cframe[2]) * row[2];
+        direction[axis] = -(ray_cframe[5] * row[0] + ray_cframe[8] * row[1] + ray_cframe[11] * row[2]);
+    }
+}
+
+// Whether the ray passes through the box between the corners bounds[0..3] and bounds[3..6] in front of its origin.
+bool hits_bounds(float *origin,
+                 float *direction,
+                 __global const float *bounds)
+{
+    float t_near = 0.0f;
+    float t_far = INFINITY;
+    for (int axis = 0; axis < 3; axis++) {
+        // Dividing by a zero direction gives infinities, which fmin and fmax handle, and NaNs, which they ignore.
+        float inverse = 1.0f / direction[axis];
+        float t0 = (bounds[axis] - origin[axis]) * inverse;
+        float t1 = (bounds[3 + axis] - origin[axis]) * inverse;
+        t_near = fmax(t_near, fmin(t0, t1));
+        t_far = fmin(t_far, fmax(t0, t1));
+    }
+    return t_near <= t_far;
+}
+
+// Nearest triangle of the mesh hit by the ray, with the watertight test of Woop, Benthin and Wald, so rays never
+// slip through the shared edges of neighbouring triangles. Returns the index of the triangle or -1, out_t is
+// the distance along the ray and out_weights the barycentric weights of the three vertices of the triangle.
+int trace_mesh(float *cframe,
+               __global const float *props,
+               __global const uint *uint_props,
+               float *ray_cframe,
+               float *out_t,
+               float *out_weights)
+{
+    float origin[3];
+    float direction[3];
+    ray_to_local(cframe, ray_cframe, origin, direction);
+    if (!hits_bounds(origin, direction, props)) {
+        return -1;
+    }
+    uint triangle_count = uint_props[0];
+    __global const float *positions = &props[MESH_BOUNDS_LEN];
+    __global const uint *indices = &uint_props[MESH_HEADER_LEN];
+    // The ray is turned so it runs along z, which is the axis it's longest along, and sheared to be parallel to it.
+    int kz = 0;
+    if (fabs(direction[1]) > fabs(direction[kz])) {
+        kz = 1;
+    }
+    if (fabs(direction[2]) > fabs(direction[kz])) {
+        kz = 2;
+    }
+    int kx = (kz + 1) % 3;
+    int ky = (kx + 1) % 3;
+    if (direction[kz] < 0.0f) {
+        int swap = kx;
+        kx = ky;
+        ky = swap;
+    }
+    float shear_x = direction[kx] / direction[kz];
+    float shear_y = direction[ky] / direction[kz];
+    float shear_z = 1.0f / direction[kz];
+    int triangle_found = -1;
+    float t = INFINITY;
+    for (uint i = 0; i < triangle_count; i++) {
+        float vertex[3][3];
+        for (int j = 0; j < 3; j++) {
+            uint index = indices[i * 3 + j];
+            for (int axis = 0; axis < 3; axis++) {
+                vertex[j][axis] = positions[index * 3 + axis] - origin[axis];
+            }
+        }
+        float ax = vertex[0][kx] - shear_x * vertex[0][kz];
+        float ay = vertex[0][ky] - shear_y * vertex[0][kz];
+        float bx = vertex[1][kx] - shear_x * vertex[1][kz];
+        float by = vertex[1][ky] - shear_y * vertex[1][kz];
+        float cx = vertex[2][kx] - shear_x * vertex[2][kz];
+        float cy = vertex[2][ky] - shear_y * vertex[2][kz];
+        float u = cx * by - cy * bx;
+        float v = ax * cy - ay * cx;
+        float w = bx * ay - by * ax;
+        // The ray only passes through the triangle when it's on the same side of all three edges.
+        if ((u < 0.0f || v < 0.0f || w < 0.0f) && (u > 0.0f || v > 0.0f || w > 0.0f)) {
+            continue;
+        }
+        float det = u + v + w;
+        if (det == 0.0f) {
+            continue;
+        }
+        float local_t = (u * vertex[0][kz] + v * vertex[1][kz] + w * vertex[2][kz]) * shear_z / det;
+        if (local_t > 0.0f && local_t < t) {
+            t = local_t;
+            triangle_found = i;
+            out_weights[0] = u / det;
+            out_weights[1] = v / det;
+            out_weights[2] = w / det;
+        }
+    }
+    *out_t = t;
+    return triangle_found;
+}
+
+// Normal where the ray hits triangle of the mesh at the barycentric weights, blended between the vertex normals when the
+// mesh has them, which decide what is outside. Otherwise it's the normal of the triangle facing the ray, so both sides
+// of a triangle are lit the same.
+void mesh_normal(float *cframe,
+                 __global const float *props,
+                 __global const uint *uint_props,
+                 float *ray_cframe,
+                 int triangle,
+                 float *weights,
+                 float *out_normal)
+{
+    uint vertex_count = uint_props[1];
+    uint flags = uint_props[2];
+    __global const float *positions = &props[MESH_BOUNDS_LEN];
+    __global const float *normals = &positions[vertex_count * 3];
+    __global const uint *indices = &uint_props[MESH_HEADER_LEN];
+    uint index[3] = { indices[triangle * 3], indices[triangle * 3 + 1], indices[triangle * 3 + 2] };
+    float edge1[3];
+    float edge2[3];
+    for (int axis = 0; axis < 3; axis++) {
+        edge1[axis] = positions[index[1] * 3 + axis] - positions[index[0] * 3 + axis];
+        edge2[axis] = positions[index[2] * 3 + axis] - positions[index[0] * 3 + axis];
+    }
+    float face_normal[3] = { edge1[1] * edge2[2] - edge1[2] * edge2[1], edge1[2] * edge2[0] - edge1[0] * edge2[2], edge1[0] * edge2[1] - edge1[1] * edge2[0] };
+    float local_normal[3] = { face_normal[0], face_normal[1], face_normal[2] };
+    if (flags & MESH_HAS_NORMALS) {
+        for (int axis = 0; axis < 3; axis++) {
+            local_normal[axis] = weights[0] * normals[index[0] * 3 + axis] + weights[1] * normals[index[1] * 3 + axis] + weights[2] * normals[index[2] * 3 + axis];
+        }
+    } else {
+        float origin[3];
+        float direction[3];
+        ray_to_local(cframe, ray_cframe, origin, direction);
+        if (face_normal[0] * direction[0] + face_normal[1] * direction[1] + face_normal[2] * direction[2] > 0.0f) {
+            local_normal[0] = -local_normal[0];
+            local_normal[1] = -local_normal[1];
+            local_normal[2] = -local_normal[2];
+        }
+    }
+    for (int axis = 0; axis < 3; axis++) {
+        out_normal[axis] = local_normal[0] * cframe[3 + axis] + local_normal[1] * cframe[6 + axis] + local_normal[2] * cframe[9 + axis];
+    }
+    normalize_vector(out_normal);
+}
+
+// Where the shadow ray of a hit on triangle of the mesh starts, the hit moved out onto the curved surface the vertex
+// normals describe, after Hanika's fix for the shadow terminator. Otherwise the flat triangles shadow the points
+// their blended normals still light, which shows them as jagged steps along the terminator.
+void mesh_shadow_origin(float *cframe,
+                        __global const float *props,
+                        __global const uint *uint_props,
+                        int triangle,
+                        float *weights,
+                        float *edge_pos,
+                        float *out_origin)
+{
+    uint vertex_count = uint_props[1];
+    uint flags = uint_props[2];
+    out_origin[0] = edge_pos[0];
+    out_origin[1] = edge_pos[1];
+    out_origin[2] = edge_pos[2];
+    if (!(flags & MESH_HAS_NORMALS)) {
+        return;
+    }
+    __global const float *positions = &props[MESH_BOUNDS_LEN];
+    __global const float *normals = &positions[vertex_count * 3];
+    __global const uint *indices = &uint_props[MESH_HEADER_LEN];
+    float local[3];
+    for (int axis = 0; axis < 3; axis++) {
+        float *row = &cframe[3 + axis * 3];
+        local[axis] = (edge_pos[0] - cframe[0]) * row[0] + (edge_pos[1] - cframe[1]) * row[1] + (edge_pos[2] - cframe[2]) * row[2];
+    }
+    // The hit projected onto the tangent plane of every vertex, blended like the normals.
+    float curved[3] = { 0.0f, 0.0f, 0.0f };
+    float shading_normal[3] = { 0.0f, 0.0f, 0.0f };
+    for (int j = 0; j < 3; j++) {
+        __global const float *vertex = &positions[indices[triangle * 3 + j] * 3];
+        __global const float *normal = &normals[indices[triangle * 3 + j] * 3];
+        float length_squared = normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2];
+        float distance = 0.0f;
+        if (length_squared > 0.0f) {
+            distance = ((local[0] - vertex[0]) * normal[0] + (local[1] - vertex[1]) * normal[1] + (local[2] - vertex[2]) * normal[2]) / length_squared;
+        }
+        for (int axis = 0; axis < 3; axis++) {
+            curved[axis] += weights[j] * (local[axis] - distance * normal[axis]);
+            shading_normal[axis] += weights[j] * normal[axis];
+        }
+    }
+    // Where the surface curves inwards the triangle already lies above it.
+    if ((curved[0] - local[0]) * shading_normal[0] + (curved[1] - local[1]) * shading_normal[1] + (curved[2] - local[2]) * shading_normal[2] <= 0.0f) {
+        return;
+    }
+    for (int axis = 0; axis < 3; axis++) {
+        out_origin[axis] = cframe[axis] + curved[0] * cframe[3 + axis] + curved[1] * cframe[6 + axis] + curved[2] * cframe[9 + axis];
+    }
+}
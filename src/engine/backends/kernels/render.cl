@@ -0,0 +1,265 @@
+// Entry point of the device code, every other file is pulled in through #include.
+#include "shading.cl"
+#include "sampling.cl"
+#include "camera.cl"
+#include "tonemap.cl"
+
+// Keeps the running average of every sample of a pixel as linear RGBA, where 1.0 is a fully lit channel.
+void accumulate_pixel(__global float *hdr_buffer,
+                      uint sample_index,
+                      float *color)
+{
+    size_t id = get_global_id(0);
+    for (int i = 0; i < 3; i++) {
+        float linear = color[i] / 255.0f;
+        if (sample_index == 0) {
+            hdr_buffer[id * 4 + i] = linear;
+        } else {
+            hdr_buffer[id * 4 + i] += (linear - hdr_buffer[id * 4 + i]) / (float) (sample_index + 1);
+        }
+    }
+    hdr_buffer[id * 4 + 3] = 1.0f;
+}
+
+// The same running average for the AOVs, except for the object index which can't be averaged and is
+// the one of the first sample. The averaged normal is made unit length again, unless nothing was hit.
+void accumulate_aovs(__global float *aov_buffer,
+                     uint sample_index,
+                     float *aov)
+{
+    size_t id = get_global_id(0);
+    for (int i = 0; i < AOV_LEN - 1; i++) {
+        if (sample_index == 0) {
+            aov_buffer[id * AOV_LEN + i] = aov[i];
+        } else {
+            aov_buffer[id * AOV_LEN + i] += (aov[i] - aov_buffer[id * AOV_LEN + i]) / (float) (sample_index + 1);
+        }
+    }
+    if (sample_index == 0) {
+        aov_buffer[id * AOV_LEN + AOV_LEN - 1] = aov[AOV_LEN - 1];
+    }
+    __global float *normal = &aov_buffer[id * AOV_LEN + 4];
+    float length = sqrt(normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]);
+    if (length > 0.0f) {
+        normal[0] /= length;
+        normal[1] /= length;
+        normal[2] /= length;
+    }
+}
+
+// Shades the camera ray through the point (px, py) of the image, in pixels from the top left corner.
+// Every ray is taken at a random moment of the shutter interval, which blurs whatever moves during it.
+void trace_camera_ray(float px,
+                      float py,
+                      uint *rng_state,
+                      uint width,
+                      uint height,
+                      __constant float *camera,
+                      float camera_width,
+                      float camera_height,
+                      float focal_length,
+                      __constant float *object_cframe,
+                      __constant float *object_end_cframe,
+                      __constant uchar *object_type,
+                      unsigned int object_amnt,
+                      __global const float *object_props,
+                      __constant uint *prop_offset,
+                      __global const uint *object_uint_props,
+                      __constant uint *uint_prop_offset,
+                      __constant uchar *color,
+                      __constant float *directionlight_direction,
+                      __constant uchar *directionlight_color,
+                      float *out_color,
+                      float *out_aov)
+{
+    float time = random_float(rng_state);
+    float origin[3];
+    float direction[3];
+    if (!primary_ray(px, py, width, height, camera, camera_width, camera_height, focal_length, origin, direction)) {
+        out_color[0] = 0.0f;
+        out_color[1] = 0.0f;
+        out_color[2] = 0.0f;
+        for (int i = 0; i < AOV_LEN - 1; i++) {
+            out_aov[i] = 0.0f;
+        }
+        out_aov[AOV_LEN - 1] = -1.0f;
+        return;
+    }
+    apply_lens(camera, rng_state, origin, direction);
+    float camera_cframe[12];
+    interpolate_cframe(camera, &camera[18], time, camera_cframe);
+    float ray_cframe[12];
+    camera_ray_cframe(camera_cframe, origin, direction, ray_cframe);
+    render_pixel(out_color, object_cframe, object_end_cframe, object_type, object_amnt, ray_cframe, time, object_props, prop_offset, object_uint_props, uint_prop_offset, color, directionlight_direction, directionlight_color, out_aov);
+    if (out_aov[AOV_LEN - 1] >= 0.0f) {
+        // Distance from the camera along its viewing direction, the back vector of the camera is its last row.
+        out_aov[0] = -((out_aov[1] - camera_cframe[0]) * camera_cframe[9] + (out_aov[2] - camera_cframe[1]) * camera_cframe[10] + (out_aov[3] - camera_cframe[2]) * camera_cframe[11]);
+    }
+}
+
+// Combines what the left and right eye see into a red/cyan anaglyph, with the matrices of Eric Dubois
+// that keep the colors as close to the original as the glasses allow.
+void dubois_anaglyph(float *left,
+                     float *right,
+                     float *out_color)
+{
+    out_color[0] = fmax(0.437f * left[0] + 0.449f * left[1] + 0.164f * left[2] - 0.011f * right[0] - 0.032f * right[1] - 0.007f * right[2], 0.0f);
+    out_color[1] = fmax(-0.062f * left[0] - 0.062f * left[1] - 0.024f * left[2] + 0.377f * right[0] + 0.761f * right[1] + 0.009f * right[2], 0.0f);
+    out_color[2] = fmax(-0.048f * left[0] - 0.050f * left[1] - 0.017f * left[2] - 0.026f * right[0] - 0.093f * right[1] + 1.234f * right[2], 0.0f);
+}
+
+// Shades the point (px, py) of a view, which is the image of a single eye unless both eyes are mixed into an anaglyph.
+// The AOVs of an anaglyph are those of the left eye.
+void trace_view_ray(float px,
+                    float py,
+                    uint *rng_state,
+                    uint width,
+                    uint height,
+                    __constant float *camera,
+                    uchar stereo_layout,
+                    float camera_width,
+                    float camera_height,
+                    float focal_length,
+                    __constant float *object_cframe,
+                    __constant float *object_end_cframe,
+                    __constant uchar *object_type,
+                    unsigned int object_amnt,
+                    __global const float *object_props,
+                    __constant uint *prop_offset,
+                    __global const uint *object_uint_props,
+                    __constant uint *uint_prop_offset,
+                    __constant uchar *color,
+                    __constant float *directionlight_direction,
+                    __constant uchar *directionlight_color,
+                    float *out_color,
+                    float *out_aov)
+{
+    if (stereo_layout != 3) {
+        trace_camera_ray(px, py, rng_state, width, height, camera, camera_width, camera_height, focal_length,
+                         object_cframe, object_end_cframe, object_type, object_amnt, object_props, prop_offset, object_uint_props, uint_prop_offset, color, directionlight_direction, directionlight_color, out_color, out_aov);
+        return;
+    }
+    float left[3];
+    float right[3];
+    float right_aov[AOV_LEN];
+    // Both eyes get the same lens samples.
+    uint right_rng_state = *rng_state;
+    trace_camera_ray(px, py, rng_state, width, height, camera, camera_width, camera_height, focal_length,
+                     object_cframe, object_end_cframe, object_type, object_amnt, object_props, prop_offset, object_uint_props, uint_prop_offset, color, directionlight_direction, directionlight_color, left, out_aov);
+    trace_camera_ray(px, py, &right_rng_state, width, height, &camera[CAMERA_VEC_LEN], camera_width, camera_height, focal_length,
+                     object_cframe, object_end_cframe, object_type, object_amnt, object_props, prop_offset, object_uint_props, uint_prop_offset, color, directionlight_direction, directionlight_color, right, right_aov);
+    dubois_anaglyph(left, right, out_color);
+}
+
+// Renders the tile_width wide region starting at (tile_x, tile_y) of a width x height image,
+// hdr_buffer only holds the pixels of that tile. So does aov_buffer, which is only written when it isn't null. camera holds the left and the right eye, which are
+// the same camera unless stereo_layout is 1 (side by side), 2 (top bottom) or 3 (anaglyph).
+__kernel void render(__global float *hdr_buffer,
+                     __global float *aov_buffer,
+                     uint sample_index,
+                     uint samples_per_pixel,
+                     uchar sample_pattern,
+                     uchar filter,
+                     uint width,
+                     uint height,
+                     uint tile_x,
+                     uint tile_y,
+                     uint tile_width,
+                     __constant float *camera,
+                     uchar stereo_layout,
+                     float camera_width,
+                     float camera_height,
+                     float focal_length,
+                     __constant float *object_cframe,
+                     __constant float *object_end_cframe,
+                     __constant uchar *object_type,
+                     unsigned int object_amnt,
+                     __global const float *object_props,
+                     __constant uint *prop_offset,
+                     __global const uint *object_uint_props,
+                     __constant uint *uint_prop_offset,
+                     __constant uchar *color,
+                     __constant float *directionlight_direction,
+                     __constant uchar *directionlight_color) {
+    uint x = tile_x + get_global_id(0) % tile_width;
+    uint y = tile_y + get_global_id(0) / tile_width;
+    // Random numbers follow the pixel in the full image, so tiled and untiled renders are identical.
+    uint pixel_id = y * width + x;
+    float pixel_color[3] = { 0.0f, 0.0f, 0.0f };
+    float pixel_aov[AOV_LEN];
+    uint rng_state = hash_uint(pixel_id ^ hash_uint(sample_index));
+    // The part of the frame showing the eye this pixel belongs to, samples never leave it.
+    uint view_x = x;
+    uint view_y = y;
+    uint view_width = width;
+    uint view_height = height;
+    __constant float *view_camera = camera;
+    if (stereo_layout == 1) {
+        view_width = width / 2;
+        if (x >= view_width) {
+            view_x -= view_width;
+            view_width = width - view_width;
+            view_camera = &camera[CAMERA_VEC_LEN];
+        }
+    } else if (stereo_layout == 2) {
+        view_height = height / 2;
+        if (y >= view_height) {
+            view_y -= view_height;
+            view_height = height - view_height;
+            view_camera = &camera[CAMERA_VEC_LEN];
+        }
+    }
+    if (samples_per_pixel <= 1 && sample_index == 0) {
+        // A single sample goes through the centre of the pixel.
+        trace_view_ray((float) view_x + 0.5f, (float) view_y + 0.5f, &rng_state, view_width, view_height, view_camera, stereo_layout, camera_width, camera_height, focal_length,
+                       object_cframe, object_end_cframe, object_type, object_amnt, object_props, prop_offset, object_uint_props, uint_prop_offset, color, directionlight_direction, directionlight_color, pixel_color, pixel_aov);
+    } else {
+        // Accumulated frames shift the whole pattern randomly, so every frame covers different positions.
+        float shift_x = 0.0f;
+        float shift_y = 0.0f;
+        if (sample_index > 0) {
+            shift_x = random_float(&rng_state);
+            shift_y = random_float(&rng_state);
+        }
+        float radius = filter_radius(filter);
+        float weight_sum = 0.0f;
+        for (int i = 0; i < AOV_LEN; i++) {
+            pixel_aov[i] = 0.0f;
+        }
+        for (uint s = 0; s < samples_per_pixel; s++) {
+            float u, v;
+            sample_position(sample_pattern, s, samples_per_pixel, &rng_state, &u, &v);
+            u += shift_x;
+            v += shift_y;
+            float dx = ((u - floor(u)) - 0.5f) * 2.0f * radius;
+            float dy = ((v - floor(v)) - 0.5f) * 2.0f * radius;
+            float weight = filter_weight(filter, dx, dy);
+            float sample_color[3];
+            float sample_aov[AOV_LEN];
+            trace_view_ray((float) view_x + 0.5f + dx, (float) view_y + 0.5f + dy, &rng_state, view_width, view_height, view_camera, stereo_layout, camera_width, camera_height, focal_length,
+                           object_cframe, object_end_cframe, object_type, object_amnt, object_props, prop_offset, object_uint_props, uint_prop_offset, color, directionlight_direction, directionlight_color, sample_color, sample_aov);
+            pixel_color[0] += sample_color[0] * weight;
+            pixel_color[1] += sample_color[1] * weight;
+            pixel_color[2] += sample_color[2] * weight;
+            for (int i = 0; i < AOV_LEN - 1; i++) {
+                pixel_aov[i] += sample_aov[i] * weight;
+            }
+            if (s == 0) {
+                pixel_aov[AOV_LEN - 1] = sample_aov[AOV_LEN - 1];
+            }
+            weight_sum += weight;
+        }
+        if (weight_sum > 0.0f) {
+            pixel_color[0] /= weight_sum;
+            pixel_color[1] /= weight_sum;
+            pixel_color[2] /= weight_sum;
+            for (int i = 0; i < AOV_LEN - 1; i++) {
+                pixel_aov[i] /= weight_sum;
+            }
+        }
+    }
+    accumulate_pixel(hdr_buffer, sample_index, pixel_color);
+    if (aov_buffer) {
+        accumulate_aovs(aov_buffer, sample_index, pixel_aov);
+    }
+}
@@ -0,0 +1,74 @@
+uint hash_uint(uint x)
+{
+    x ^= x >> 16;
+    x *= 0x7feb352dU;
+    x ^= x >> 15;
+    x *= 0x846ca68bU;
+    x ^= x >> 16;
+    return x;
+}
+
+// Uniform random number in [0, 1), advancing the per pixel random state.
+float random_float(uint *state)
+{
+    *state = hash_uint(*state);
+    return (float) (*state >> 8) * (1.0f / 16777216.0f);
+}
+
+// Sample position in [0, 1) x [0, 1) inside the pixel footprint, for sample out of samples_per_pixel.
+// pattern 0 is a regular grid, 1 a jittered (stratified) grid and 2 a grid rotated by atan(1/2).
+void sample_position(uchar pattern,
+                     uint sample,
+                     uint samples_per_pixel,
+                     uint *rng_state,
+                     float *u,
+                     float *v)
+{
+    // nx columns and ny rows cover the pixel, the last row holds the remaining samples spread over its full width.
+    uint nx = max((uint) ceil(sqrt((float) samples_per_pixel)), 1U);
+    uint ny = max((samples_per_pixel + nx - 1) / nx, 1U);
+    uint row = sample / nx;
+    uint row_width = (row == ny - 1) ? samples_per_pixel - nx * (ny - 1) : nx;
+    float cell_x = (float) (sample % nx);
+    float cell_y = (float) row;
+    float width = (float) max(row_width, 1U);
+    float height = (float) ny;
+    if (pattern == 1) {
+        *u = (cell_x + random_float(rng_state)) / width;
+        *v = (cell_y + random_float(rng_state)) / height;
+    } else {
+        *u = (cell_x + 0.5f) / width;
+        *v = (cell_y + 0.5f) / height;
+        if (pattern == 2) {
+            float du = *u - 0.5f;
+            float dv = *v - 0.5f;
+            float ru = du * 0.8944272f - dv * 0.4472136f + 0.5f;
+            float rv = du * 0.4472136f + dv * 0.8944272f + 0.5f;
+            *u = ru - floor(ru);
+            *v = rv - floor(rv);
+        }
+    }
+}
+
+// Half width in pixels of the reconstruction filter: 0 is box, 1 tent and 2 gaussian.
+float filter_radius(uchar filter)
+{
+    if (filter == 1) return 1.0f;
+    if (filter == 2) return 1.5f;
+    return 0.5f;
+}
+
+float filter_weight(uchar filter,
+                    float dx,
+                    float dy)
+{
+    if (filter == 1) {
+        return fmax(1.0f - fabs(dx), 0.0f) * fmax(1.0f - fabs(dy), 0.0f);
+    }
+    if (filter == 2) {
+        // Gaussian with a standard deviation of 0.5 pixels, shifted down to reach 0 at the filter radius.
+        float edge = exp(-2.0f * 1.5f * 1.5f);
+        return fmax(exp(-2.0f * dx * dx) - edge, 0.0f) * fmax(exp(-2.0f * dy * dy) - edge, 0.0f);
+    }
+    return 1.0f;
+}
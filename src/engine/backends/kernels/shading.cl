@@ -0,0 +1,123 @@
+#include "intersect.cl"
+
+// Floats per pixel of the AOVs, AOV_LEN in aov.rs: linear depth, world position, shading normal,
+// albedo and the index of the object, which is -1 where nothing was hit.
+#define AOV_LEN 11
+
+// Shades the ray at time, the shadow ray is cast at the same time so it sees the objects where the ray did.
+void render_pixel(float *out_color,
+                  __constant float* object_cframe,
+                  __constant float* object_end_cframe,
+                  __constant uchar *object_type,
+                  unsigned int object_amnt,
+                  float *ray_cframe,
+                  float time,
+                  __global const float *object_props,
+                  __constant uint *prop_offset,
+                  __global const uint *object_uint_props,
+                  __constant uint *uint_prop_offset,
+                  __constant uchar *color,
+                  __constant float *directionlight_direction,
+                  __constant uchar *directionlight_color,
+                  float *out_aov)
+{
+    // The depth is left to the caller, which knows where the camera is.
+    for (int i = 0; i < AOV_LEN - 1; i++) {
+        out_aov[i] = 0.0f;
+    }
+    out_aov[AOV_LEN - 1] = -1.0f;
+    float t;
+    int primitive;
+    float weights[3];
+    int intersection_index = intersect_objects(object_cframe,
+                                               object_end_cframe,
+                                               object_type,
+                                               object_amnt,
+                                               ray_cframe,
+                                               time,
+                                               object_props,
+                                               prop_offset,
+                                               object_uint_props,
+                                               uint_prop_offset,
+                                               &t,
+                                               &primitive,
+                                               weights);
+
+    if (intersection_index >= 0)
+    {
+        float edge_pos[3] = { ray_cframe[0] - (ray_cframe[5] * t), ray_cframe[1] - (ray_cframe[8] * t), ray_cframe[2] - (ray_cframe[11] * t) };
+        float normal[3] = { 0.0f, 0.0f, 0.0f };
+        calculate_normal_vector(object_cframe,
+                                object_end_cframe,
+                                object_type,
+                                intersection_index,
+                                time,
+                                object_props,
+                                prop_offset,
+                                object_uint_props,
+                                uint_prop_offset,
+                                ray_cframe,
+                                edge_pos,
+                                primitive,
+                                weights,
+                                normal);
+        for (int i = 0; i < 3; i++) {
+            out_aov[1 + i] = edge_pos[i];
+            out_aov[4 + i] = normal[i];
+            out_aov[7 + i] = (float) color[intersection_index * 3 + i] / 255.0f;
+        }
+        out_aov[AOV_LEN - 1] = (float) intersection_index;
+        // The calculated edge_pos can be slightly inside inside the object, causing the ray to calculate the shadow to collide with the object itself.
+        // This is due to floating point precision.
+        // To combat this, take the starting point of the ray at a distance of "correction_factor" more outwards of the object.
+        float shadow_origin[3] = { edge_pos[0], edge_pos[1], edge_pos[2] };
+        // Only meshes have primitives, see intersect_object.
+        if (primitive >= 0) {
+            float cframe[12];
+            interpolate_cframe(&object_cframe[intersection_index * 12], &object_end_cframe[intersection_index * 12], time, cframe);
+            mesh_shadow_origin(cframe, &object_props[prop_offset[intersection_index]], &object_uint_props[uint_prop_offset[intersection_index]],
+                               primitive, weights, edge_pos, shadow_origin);
+        }
+        float correction_factor = 0.01;
+        float corrected_edge_pos[3] = { shadow_origin[0] + (normal[0] * correction_factor), shadow_origin[1] + (normal[1] * correction_factor), shadow_origin[2] + (normal[2] * correction_factor) };
+        float edge_to_dir_light[12] = { corrected_edge_pos[0], corrected_edge_pos[1], corrected_edge_pos[2],
+                                        0.0, 0.0, directionlight_direction[0],
+                                        0.0, 0.0, directionlight_direction[1],
+                                        0.0, 0.0, directionlight_direction[2] };
+        float dl_t;
+        int dl_primitive;
+        float dl_weights[3];
+        int dl_int_index = intersect_objects(object_cframe,
+                                             object_end_cframe,
+                                             object_type,
+                                             object_amnt,
+                                             edge_to_dir_light,
+                                             time,
+                                             object_props,
+                                             prop_offset,
+                                             object_uint_props,
+                                             uint_prop_offset,
+                                             &dl_t,
+                                             &dl_primitive,
+                                             dl_weights);
+        // A shadow ray hitting the object it starts on still counts as lit, except on meshes.
+        // Those can shadow themselves and start on the smooth surface instead, see mesh_shadow_origin.
+        if (dl_int_index < 0 || (dl_int_index == intersection_index && object_type[intersection_index] != RENDER_TYPE_MESH))
+        {
+            float diffuseFactor = fmax(normal[0] * (-directionlight_direction[0]) + normal[1] * (-directionlight_direction[1]) + normal[2] * (-directionlight_direction[2]), 0.0f);
+            float directional_diffuse_light_color[3] = { directionlight_color[0] * diffuseFactor / 0xff, directionlight_color[1] * diffuseFactor / 0xff, directionlight_color[2] * diffuseFactor / 0xff };
+
+            out_color[0] = ((float) color[intersection_index * 3]) * directional_diffuse_light_color[0];
+            out_color[1] = ((float) color[intersection_index * 3 + 1]) * directional_diffuse_light_color[1];
+            out_color[2] = ((float) color[intersection_index * 3 + 2]) * directional_diffuse_light_color[2];
+        } else {
+            out_color[0] = 0.0f;
+            out_color[1] = 0.0f;
+            out_color[2] = 0.0f;
+        }
+    } else {
+        out_color[0] = 0.0f;
+        out_color[1] = 0.0f;
+        out_color[2] = 0.0f;
+    }
+}
@@ -0,0 +1,36 @@
+// tone_map_operator 0 clamps, 1 is Reinhard and 2 the ACES filmic curve.
+float tone_map_channel(uchar tone_map_operator, float c)
+{
+    switch (tone_map_operator) {
+        case 1:
+            return c / (1.0f + c);
+        case 2:
+            // ACES filmic curve fit by Krzysztof Narkowicz.
+            return clamp((c * (2.51f * c + 0.03f)) / (c * (2.43f * c + 0.59f) + 0.14f), 0.0f, 1.0f);
+        default:
+            return clamp(c, 0.0f, 1.0f);
+    }
+}
+
+float linear_to_srgb(float c)
+{
+    if (c <= 0.0031308f) {
+        return 12.92f * c;
+    }
+    return 1.055f * pow(c, 1.0f / 2.4f) - 0.055f;
+}
+
+// Turns the linear hdr_buffer into the 8 bit sRGB output_buffer, after scaling it by 2^exposure.
+__kernel void tone_map(__global float *hdr_buffer,
+                       __global uchar *output_buffer,
+                       uchar tone_map_operator,
+                       float exposure)
+{
+    size_t id = get_global_id(0);
+    float scale = exp2(exposure);
+    for (int i = 0; i < 3; i++) {
+        float mapped = clamp(tone_map_channel(tone_map_operator, max(hdr_buffer[id * 4 + i] * scale, 0.0f)), 0.0f, 1.0f);
+        output_buffer[id * 4 + i] = (uchar) (linear_to_srgb(mapped) * 255.0f + 0.5f);
+    }
+    output_buffer[id * 4 + 3] = 0xff;
+}
@@ -0,0 +1,5 @@
+pub mod opencl;
+pub mod devices;
+pub mod cpu;
+pub mod kernel_source;
+pub mod multi;
\ No newline at end of file
@@ -0,0 +1,398 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread;
+use crate::engine::error::RendererError;
+use crate::engine::render::RenderObject;
+use crate::engine::camera::Camera;
+use crate::engine::stereo::StereoRig;
+use crate::engine::aov::AOV_LEN;
+use crate::engine::renderer::{RenderBackend, TileCallback};
+use crate::engine::backends::opencl::OpenCLBackend;
+use crate::engine::backends::devices::{DeviceSelector, select_all_devices};
+use crate::engine::backends::kernel_source::KernelSource;
+use crate::engine::sampling::AntiAliasing;
+use crate::engine::tiling::{Tile, TileQueue};
+use crate::engine::tonemapping::ToneMapping;
+use crate::engine::stats::FrameStats;
+
+// How much a new throughput measurement moves the running estimate of a device.
+const THROUGHPUT_SMOOTHING: f32 = 0.3;
+// Bands are only moved when one of them would change by more than this fraction of the frame height,
+// every move reallocates the buffers of the devices involved.
+const REBALANCE_THRESHOLD: f32 = 0.02;
+
+// Splits every frame into horizontal bands, one per OpenCL device, and merges their pixels into one frame.
+// Band heights follow the throughput measured on each device, so faster devices get more rows.
+pub struct MultiDeviceBackend {
+    width: u32,
+    height: u32,
+    // Every device found is used when this is empty.
+    device_selectors: Vec<DeviceSelector>,
+    kernel_source: KernelSource,
+    devices: Vec<OpenCLBackend>,
+    // Rows per second each device managed on its recent frames, None until it rendered one.
+    throughput: Vec<Option<f32>>,
+    bands: Vec<Tile>,
+    // Bands of every frame in flight, they can change before the frame is collected.
+    pending_frames: VecDeque<Vec<Tile>>,
+}
+
+// Splits height rows into one band per weight, in proportion to the weights and at least one row each.
+fn split_into_bands(width: u32, height: u32, weights: &[f32]) -> Vec<Tile> {
+    let total: f32 = weights.iter().sum();
+    let mut bands = Vec::new();
+    let mut y = 0;
+    let mut cumulative = 0f32;
+    for (i, weight) in weights.iter().enumerate() {
+        cumulative += weight;
+        let rows_left_for_others = (weights.len() - i - 1) as u32;
+        let end = if i + 1 == weights.len() {
+            height
+        } else {
+            ((cumulative / total * height as f32).round() as u32).clamp(y + 1, height.saturating_sub(rows_left_for_others).max(y + 1))
+        };
+        bands.push(Tile::new(0, y, width, end - y));
+        y = end;
+    }
+    bands
+}
+
+// Submits a frame to every device in turn. When one of them fails, the frame is discarded again on the devices
+// before it, so the devices keep the same frames in flight and later frames don't merge bands of different frames.
+fn submit_to_all<D>(devices: &mut [D], mut submit: impl FnMut(&mut D) -> Result<(), RendererError>, mut discard: impl FnMut(&mut D)) -> Result<(), RendererError> {
+    for i in 0..devices.len() {
+        if let Err(err) = submit(&mut devices[i]) {
+            for device in devices[..i].iter_mut() {
+                discard(device);
+            }
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+impl MultiDeviceBackend {
+    pub fn new(width: u32, height: u32, device_selectors: Vec<DeviceSelector>, kernel_source: KernelSource) -> Self {
+        Self {
+            width,
+            height,
+            device_selectors,
+            kernel_source,
+            devices: Vec::new(),
+            throughput: Vec::new(),
+            bands: Vec::new(),
+            pending_frames: VecDeque::new(),
+         }
+    }
+
+    fn assign_bands(&mut self, bands: Vec<Tile>) {
+        for (device, band) in self.devices.iter_mut().zip(bands.iter()) {
+            device.set_region(Some(*band));
+        }
+        self.bands = bands;
+    }
+
+    // Moves rows from slow to fast devices. Only done while the devices aren't accumulating a still image,
+    // since a new band starts its accumulation over.
+    fn rebalance(&mut self) {
+        if self.devices.iter().any(|device| device.get_accumulated_samples() > 1) {
+            return;
+        }
+        let weights: Vec<f32> = match self.throughput.iter().copied().collect::<Option<Vec<f32>>>() {
+            Some(weights) => weights,
+            None => return,
+        };
+        let bands = split_into_bands(self.width, self.height, &weights);
+        let threshold = (self.height as f32 * REBALANCE_THRESHOLD).max(1f32);
+        let moved = bands.iter().zip(self.bands.iter()).any(|(band, old_band)| (band.height as f32 - old_band.height as f32).abs() > threshold);
+        if moved {
+            log::debug!("Rebalanced device bands to {:?}", bands.iter().map(|band| band.height).collect::<Vec<u32>>());
+            self.assign_bands(bands);
+        }
+    }
+
+    // Waits for the oldest frame of every device in turn, blocking on the event that reads it back. How long a device
+    // was busy with its band comes from the profiling info of its events, which waiting on the others doesn't skew.
+    fn collect_bands(&mut self, bands: &[Tile]) -> Result<Vec<(Vec<u8>, FrameStats)>, RendererError> {
+        let mut parts = Vec::with_capacity(self.devices.len());
+        for (i, device) in self.devices.iter_mut().enumerate() {
+            let (pixels, stats) = device.wait_frame()?.ok_or(RendererError::Unknown)?;
+            // A converged band is only tone mapped, which says nothing about how fast the device renders.
+            if stats.rays_traced > 0 {
+                let busy = stats.get_total_time().as_secs_f32().max(1e-6);
+                let measured = bands[i].height as f32 / busy;
+                self.throughput[i] = Some(match self.throughput[i] {
+                    Some(throughput) => throughput + (measured - throughput) * THROUGHPUT_SMOOTHING,
+                    None => measured,
+                });
+            }
+            parts.push((pixels, stats));
+        }
+        Ok(parts)
+    }
+}
+
+impl RenderBackend for MultiDeviceBackend {
+    fn init(&mut self) -> Result<(), RendererError> {
+        let device_selectors = match self.device_selectors.is_empty() {
+            true => select_all_devices()?,
+            false => self.device_selectors.clone(),
+        };
+        if device_selectors.is_empty() {
+            return Err(RendererError::DeviceNotFoundError(String::from("any")));
+        }
+        if self.height < device_selectors.len() as u32 {
+            return Err(RendererError::DimensionsTooSmallError);
+        }
+        self.devices.clear();
+        for device_selector in device_selectors {
+            let mut device = OpenCLBackend::new_with_kernel_source(self.width, self.height, device_selector, self.kernel_source.clone());
+            device.init()?;
+            self.devices.push(device);
+        }
+        self.pending_frames.clear();
+        self.throughput = vec![None; self.devices.len()];
+        // Without measurements yet, every device starts with an equal share.
+        let bands = split_into_bands(self.width, self.height, &vec![1f32; self.devices.len()]);
+        self.assign_bands(bands);
+        Ok(())
+    }
+
+    fn submit_frame(&mut self, camera: Camera, render_objects: Vec<RenderObject>, directionlight_direction: Vec<f32>, directionlight_color: Vec<u8>) -> Result<(), RendererError> {
+        if self.bands.is_empty() {
+            return Err(RendererError::RendererNotInitializedError);
+        }
+        submit_to_all(&mut self.devices,
+                      |device| device.submit_frame(camera, render_objects.clone(), directionlight_direction.clone(), directionlight_color.clone()),
+                      |device| device.discard_last_frame())?;
+        self.pending_frames.push_back(self.bands.clone());
+        Ok(())
+    }
+
+    fn wait_frame(&mut self) -> Result<Option<(Vec::<u8>, FrameStats)>, RendererError> {
+        let bands = match self.pending_frames.pop_front() {
+            Some(bands) => bands,
+            None => return Ok(None),
+        };
+        let parts = self.collect_bands(&bands)?;
+        // The bands are stacked top to bottom and span the full width, so they can simply be appended.
+        let mut frame = Vec::with_capacity((self.width as usize) * (self.height as usize) * 4);
+        for (part, _) in parts.iter() {
+            frame.extend_from_slice(part);
+        }
+        let stats: Vec<FrameStats> = parts.iter().map(|(_, stats)| *stats).collect();
+        self.rebalance();
+        Ok(Some((frame, FrameStats::merge_parallel(&stats))))
+    }
+
+    fn get_frames_in_flight(&self) -> usize {
+        self.pending_frames.len()
+    }
+
+    // Tiles are handed out one by one to whichever device is free, which balances the load by itself. Every device
+    // uploads the scene once and renders all of its tiles on one worker, since reading a tile back blocks.
+    fn render_tiles(&mut self, camera: Camera, render_objects: Vec<RenderObject>, directionlight_direction: Vec<f32>, directionlight_color: Vec<u8>, tiles: &[Tile], on_tile: &mut TileCallback) -> Result<(), RendererError> {
+        let queue = TileQueue::new(tiles);
+        let first_error = Mutex::new(None);
+        let (sender, receiver) = mpsc::channel::<(Tile, Vec<u8>, Option<Vec<f32>>)>();
+        thread::scope(|s| {
+            for device in self.devices.iter_mut() {
+                let sender = sender.clone();
+                let (queue, first_error) = (&queue, &first_error);
+                let (render_objects, directionlight_direction, directionlight_color) = (render_objects.clone(), directionlight_direction.clone(), directionlight_color.clone());
+                s.spawn(move || {
+                    let result = device.render_tile_queue(camera, render_objects, directionlight_direction, directionlight_color, queue, &mut |tile, pixels, aovs| {
+                        let _ = sender.send((*tile, pixels.to_vec(), aovs.map(|aovs| aovs.to_vec())));
+                    });
+                    if let Err(err) = result {
+                        first_error.lock().unwrap().get_or_insert(err);
+                        // Stop the other devices as well.
+                        queue.stop();
+                    }
+                });
+            }
+            drop(sender);
+            for (tile, pixels, aovs) in receiver.iter() {
+                on_tile(&tile, &pixels, aovs.as_deref());
+            }
+        });
+        match first_error.into_inner().unwrap() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn resize(&mut self, width: u32, height: u32) -> Result<(), RendererError> {
+        if height < self.devices.len() as u32 {
+            return Err(RendererError::DimensionsTooSmallError);
+        }
+        self.width = width;
+        self.height = height;
+        self.pending_frames.clear();
+        for device in self.devices.iter_mut() {
+            device.resize(width, height)?;
+        }
+        let weights: Vec<f32> = self.throughput.iter().map(|throughput| throughput.unwrap_or(1f32)).collect();
+        let bands = split_into_bands(width, height, &weights);
+        self.assign_bands(bands);
+        Ok(())
+    }
+
+    fn read_hdr_frame(&mut self) -> Result<Vec::<f32>, RendererError> {
+        let mut frame = Vec::with_capacity((self.width as usize) * (self.height as usize) * 4);
+        for device in self.devices.iter_mut() {
+            frame.extend_from_slice(&device.read_hdr_frame()?);
+        }
+        Ok(frame)
+    }
+
+    fn read_aov_frame(&mut self) -> Result<Vec::<f32>, RendererError> {
+        let mut frame = Vec::with_capacity((self.width as usize) * (self.height as usize) * AOV_LEN);
+        for device in self.devices.iter_mut() {
+            frame.extend_from_slice(&device.read_aov_frame()?);
+        }
+        Ok(frame)
+    }
+
+    fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        for device in self.devices.iter_mut() {
+            device.set_tone_mapping(tone_mapping);
+        }
+    }
+
+    fn set_accumulation(&mut self, enabled: bool) {
+        for device in self.devices.iter_mut() {
+            device.set_accumulation(enabled);
+        }
+    }
+
+    fn reset_accumulation(&mut self) {
+        for device in self.devices.iter_mut() {
+            device.reset_accumulation();
+        }
+    }
+
+    fn get_accumulated_samples(&self) -> u32 {
+        self.devices.iter().map(|device| device.get_accumulated_samples()).min().unwrap_or(0)
+    }
+
+    fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) {
+        for device in self.devices.iter_mut() {
+            device.set_anti_aliasing(anti_aliasing);
+        }
+    }
+
+    fn set_stereo(&mut self, stereo: Option<StereoRig>) {
+        for device in self.devices.iter_mut() {
+            device.set_stereo(stereo);
+        }
+    }
+
+    fn set_aovs(&mut self, enabled: bool) {
+        for device in self.devices.iter_mut() {
+            device.set_aovs(enabled);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::backends::cpu::CpuBackend;
+    use crate::engine::world::World;
+    use crate::engine::sphere::Sphere;
+    use crate::engine::plane::Plane;
+    use crate::engine::render::Renderable;
+    use crate::engine::cframe::Positionable;
+    use crate::engine::tiling::split_into_tiles;
+
+    fn test_scene() -> (Camera, Vec<RenderObject>, Vec<f32>, Vec<u8>) {
+        let mut world = World::new();
+        let mut sphere = Sphere::new(3f32);
+        let mut floor = Plane::new();
+        sphere.set_position(0f32, 1f32, -15f32);
+        floor.set_position(0f32, -2f32, 0f32);
+        sphere.set_color(0xffu8, 0x00u8, 0x00u8);
+        floor.set_color(0x00u8, 0x00u8, 0xffu8);
+        world.push_renderable(Box::new(sphere));
+        world.push_renderable(Box::new(floor));
+        (Camera::new(90f32, 0.1f32), world.get_render_objects(), world.get_direction_light_direction_vec(), world.get_direction_light_color_vec())
+    }
+
+    #[test]
+    fn bands_follow_the_weights() {
+        assert_eq!(split_into_bands(64, 100, &[1f32, 3f32]), vec![Tile::new(0, 0, 64, 25), Tile::new(0, 25, 64, 75)]);
+        // Every device keeps at least one row.
+        let heights: Vec<u32> = split_into_bands(64, 3, &[1f32, 1000f32, 1f32]).iter().map(|band| band.height).collect();
+        assert_eq!(heights, vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn failed_submit_leaves_no_frame_on_the_other_devices() {
+        // Whether a device fails to submit, and the frames it has in flight. Every device already has frame 0.
+        let mut devices = vec![(false, vec![0]), (true, vec![0]), (false, vec![0])];
+        let result = submit_to_all(&mut devices, |(fails, frames)| {
+            if *fails {
+                return Err(RendererError::FramesInFlightError);
+            }
+            frames.push(1);
+            Ok(())
+        }, |(_, frames)| {
+            frames.pop();
+        });
+        assert!(matches!(result, Err(RendererError::FramesInFlightError)));
+        assert!(devices.iter().all(|(_, frames)| *frames == vec![0]));
+
+        devices[1].0 = false;
+        submit_to_all(&mut devices, |(_, frames)| {
+            frames.push(1);
+            Ok(())
+        }, |(_, frames)| {
+            frames.pop();
+        }).unwrap();
+        assert!(devices.iter().all(|(_, frames)| *frames == vec![0, 1]));
+    }
+
+    // Needs an OpenCL runtime, so it only runs when asked for. POCL provides one on any machine, with two devices:
+    // POCL_DEVICES="cpu cpu" cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn devices_render_the_same_frame_as_the_cpu_on_pocl() {
+        let (width, height) = (64, 48);
+        let (camera, render_objects, directionlight_direction, directionlight_color) = test_scene();
+        let pocl = DeviceSelector { platform: Some(String::from("portable")), device: None };
+        let mut multi = MultiDeviceBackend::new(width, height, vec![pocl.clone(), pocl], KernelSource::default());
+        multi.init().unwrap();
+        multi.set_accumulation(false);
+        let mut cpu = CpuBackend::new(width, height);
+        cpu.init().unwrap();
+        cpu.set_accumulation(false);
+
+        // A few frames, so the bands are rebalanced with the measured throughput in between.
+        let mut frame = Vec::new();
+        for _ in 0..4 {
+            multi.submit_frame(camera, render_objects.clone(), directionlight_direction.clone(), directionlight_color.clone()).unwrap();
+            frame = multi.wait_frame().unwrap().unwrap().0;
+        }
+        cpu.submit_frame(camera, render_objects.clone(), directionlight_direction.clone(), directionlight_color.clone()).unwrap();
+        let expected = cpu.wait_frame().unwrap().unwrap().0;
+        assert_eq!(frame.len(), expected.len());
+        let differing = frame.chunks(4).zip(expected.chunks(4))
+            .filter(|(pixel, expected)| pixel.iter().zip(expected.iter()).any(|(a, b)| a.abs_diff(*b) > 2))
+            .count();
+        assert!(differing * 100 < frame.len() / 4, "{} pixels differ from the CPU backend", differing);
+
+        // The tiles come back in whatever order the devices finish them, put together they are the same frame.
+        let mut tiled = vec![0u8; frame.len()];
+        let row_len = width as usize * 4;
+        multi.render_tiles(camera, render_objects, directionlight_direction, directionlight_color, &split_into_tiles(width, height, 16), &mut |tile, pixels, _| {
+            let tile_row_len = tile.width as usize * 4;
+            for (row, tile_row) in pixels.chunks(tile_row_len).enumerate() {
+                let start = (tile.y as usize + row) * row_len + tile.x as usize * 4;
+                tiled[start..start + tile_row_len].copy_from_slice(tile_row);
+            }
+        }).unwrap();
+        assert_eq!(tiled, frame);
+    }
+}
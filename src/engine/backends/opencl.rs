@@ -0,0 +1,678 @@
+
+extern crate ocl;
+use std::collections::VecDeque;
+use std::time::Duration;
+use ocl::{ProQue, Program, Buffer, Event, EventList, MemFlags, Queue, OclPrm, CommandQueueProperties};
+use ocl::enums::ProfilingInfo;
+use ocl::Error as OclError;
+use crate::engine::error::RendererError;
+use crate::engine::render::{RenderObject, PackedScene};
+use crate::engine::camera::{Camera, CAMERA_VEC_LEN};
+use crate::engine::stereo::{stereo_camera_vec, StereoLayout, StereoRig};
+use crate::engine::renderer::{RenderBackend, TileCallback, MAX_FRAMES_IN_FLIGHT};
+use crate::engine::backends::devices::DeviceSelector;
+use crate::engine::backends::kernel_source::{KernelSource, KernelWatcher};
+use crate::engine::accumulation::Accumulation;
+use crate::engine::sampling::AntiAliasing;
+use crate::engine::tiling::{Tile, TileQueue};
+use crate::engine::tonemapping::ToneMapping;
+use crate::engine::stats::FrameStats;
+use crate::engine::aov::AOV_LEN;
+
+// The error of a failed build contains the build log, which is what's needed to fix the kernel.
+fn log_build_error(e: OclError) -> RendererError {
+    log::error!("{}", e);
+    RendererError::KernelBuildError(e)
+}
+
+// Time the device spent executing the command of event, zero when the queue can't profile it.
+fn profiled_time(event: &Event) -> Duration {
+    let time = |info| event.profiling_info(info).ok().and_then(|result| result.time().ok());
+    match (time(ProfilingInfo::Start), time(ProfilingInfo::End)) {
+        (Some(start), Some(end)) => Duration::from_nanos(end.saturating_sub(start)),
+        _ => Duration::ZERO,
+    }
+}
+
+fn profiled_time_of_all(events: &EventList) -> Duration {
+    events.iter().map(profiled_time).sum()
+}
+
+// Number of objects, or object parameters, the scene buffers get room for when they have to be (re)allocated.
+fn scene_capacity_for(amnt: usize) -> usize {
+    amnt.max(1).next_power_of_two()
+}
+
+// Object buffers that stay resident on the device between frames. `uploaded` mirrors their current content,
+// so a new frame only has to write the objects that differ from it.
+struct SceneBuffers {
+    capacity: usize,
+    prop_capacity: usize,
+    uint_prop_capacity: usize,
+    cframe_buffer: Buffer<f32>,
+    end_cframe_buffer: Buffer<f32>,
+    render_type_buffer: Buffer<u8>,
+    object_prop_buffer: Buffer<f32>,
+    prop_offset_buffer: Buffer<u32>,
+    object_uint_prop_buffer: Buffer<u32>,
+    uint_prop_offset_buffer: Buffer<u32>,
+    color_buffer: Buffer<u8>,
+    uploaded: PackedScene,
+}
+
+impl SceneBuffers {
+    fn new(queue: &Queue, capacity: usize, prop_capacity: usize, uint_prop_capacity: usize) -> Result<Self, RendererError> {
+        let cframe_buffer = Buffer::builder().queue(queue.clone())
+            .flags(MemFlags::new().read_only())
+            .len(capacity * 12)
+            .build().map_err(RendererError::CreateBufferError)?;
+        let end_cframe_buffer = Buffer::builder().queue(queue.clone())
+            .flags(MemFlags::new().read_only())
+            .len(capacity * 12)
+            .build().map_err(RendererError::CreateBufferError)?;
+        let render_type_buffer = Buffer::builder().queue(queue.clone())
+            .flags(MemFlags::new().read_only())
+            .len(capacity)
+            .build().map_err(RendererError::CreateBufferError)?;
+        let object_prop_buffer = Buffer::builder().queue(queue.clone())
+            .flags(MemFlags::new().read_only())
+            .len(prop_capacity)
+            .build().map_err(RendererError::CreateBufferError)?;
+        let prop_offset_buffer = Buffer::builder().queue(queue.clone())
+            .flags(MemFlags::new().read_only())
+            .len(capacity)
+            .build().map_err(RendererError::CreateBufferError)?;
+        let object_uint_prop_buffer = Buffer::builder().queue(queue.clone())
+            .flags(MemFlags::new().read_only())
+            .len(uint_prop_capacity)
+            .build().map_err(RendererError::CreateBufferError)?;
+        let uint_prop_offset_buffer = Buffer::builder().queue(queue.clone())
+            .flags(MemFlags::new().read_only())
+            .len(capacity)
+            .build().map_err(RendererError::CreateBufferError)?;
+        let color_buffer = Buffer::builder().queue(queue.clone())
+            .flags(MemFlags::new().read_only())
+            .len(capacity * 3)
+            .build().map_err(RendererError::CreateBufferError)?;
+        Ok(Self {
+            capacity,
+            prop_capacity,
+            uint_prop_capacity,
+            cframe_buffer,
+            end_cframe_buffer,
+            render_type_buffer,
+            object_prop_buffer,
+            prop_offset_buffer,
+            object_uint_prop_buffer,
+            uint_prop_offset_buffer,
+            color_buffer,
+            uploaded: PackedScene::default(),
+        })
+    }
+
+    // Buffers are reallocated when the scene outgrows them, or shrinks to well below their size.
+    fn fits(&self, scene: &PackedScene) -> bool {
+        let object_amnt = scene.object_amnt as usize;
+        let prop_amnt = scene.object_props.len();
+        let uint_prop_amnt = scene.object_uint_props.len();
+        object_amnt <= self.capacity && scene_capacity_for(object_amnt) * 4 > self.capacity
+            && prop_amnt <= self.prop_capacity && scene_capacity_for(prop_amnt) * 4 > self.prop_capacity
+            && uint_prop_amnt <= self.uint_prop_capacity && scene_capacity_for(uint_prop_amnt) * 4 > self.uint_prop_capacity
+    }
+
+    // Writes every object that changed since the last upload, batching neighbouring objects into one write.
+    // Returns the amount of objects that were uploaded and whether the scene changed at all, which includes
+    // objects that were removed. The event of every write is added to events.
+    fn upload(&mut self, scene: PackedScene, events: &mut EventList) -> Result<(usize, bool), RendererError> {
+        let object_amnt = scene.object_amnt as usize;
+        let amnt_changed = scene.object_amnt != self.uploaded.object_amnt;
+        let dirty: Vec<bool> = (0..object_amnt).map(|i| !self.uploaded.object_equals(&scene, i)).collect();
+        let mut uploaded_amnt = 0;
+        let mut start = 0;
+        while start < object_amnt {
+            if !dirty[start] {
+                start += 1;
+                continue;
+            }
+            let mut end = start;
+            while end < object_amnt && dirty[end] {
+                end += 1;
+            }
+            self.cframe_buffer.write(&scene.cframes[start * 12..end * 12]).offset(start * 12).enew(&mut *events).enq()
+                .map_err(RendererError::WriteBufferError)?;
+            self.end_cframe_buffer.write(&scene.end_cframes[start * 12..end * 12]).offset(start * 12).enew(&mut *events).enq()
+                .map_err(RendererError::WriteBufferError)?;
+            self.render_type_buffer.write(&scene.render_types[start..end]).offset(start).enew(&mut *events).enq()
+                .map_err(RendererError::WriteBufferError)?;
+            let (prop_start, _) = scene.get_prop_range(start);
+            let (_, prop_end) = scene.get_prop_range(end - 1);
+            if prop_end > prop_start {
+                self.object_prop_buffer.write(&scene.object_props[prop_start..prop_end]).offset(prop_start).enew(&mut *events).enq()
+                    .map_err(RendererError::WriteBufferError)?;
+            }
+            self.prop_offset_buffer.write(&scene.prop_offsets[start..end]).offset(start).enew(&mut *events).enq()
+                .map_err(RendererError::WriteBufferError)?;
+            let (uint_prop_start, _) = scene.get_uint_prop_range(start);
+            let (_, uint_prop_end) = scene.get_uint_prop_range(end - 1);
+            if uint_prop_end > uint_prop_start {
+                self.object_uint_prop_buffer.write(&scene.object_uint_props[uint_prop_start..uint_prop_end]).offset(uint_prop_start).enew(&mut *events).enq()
+                    .map_err(RendererError::WriteBufferError)?;
+            }
+            self.uint_prop_offset_buffer.write(&scene.uint_prop_offsets[start..end]).offset(start).enew(&mut *events).enq()
+                .map_err(RendererError::WriteBufferError)?;
+            self.color_buffer.write(&scene.colors[start * 3..end * 3]).offset(start * 3).enew(&mut *events).enq()
+                .map_err(RendererError::WriteBufferError)?;
+            uploaded_amnt += end - start;
+            start = end;
+        }
+        self.uploaded = scene;
+        Ok((uploaded_amnt, uploaded_amnt > 0 || amnt_changed))
+    }
+}
+
+// Camera parameters the render kernel takes next to the camera buffer.
+struct KernelCamera {
+    stereo_layout: u8,
+    camera_width: f32,
+    camera_height: f32,
+    focal_length: f32,
+}
+
+impl KernelCamera {
+    fn new(camera: &mut Camera, stereo_layout: u8, width: u32, height: u32) -> Self {
+        let (camera_width, camera_height) = camera.get_camera_dimensions(width, height);
+        Self {
+            stereo_layout,
+            camera_width,
+            camera_height,
+            focal_length: camera.get_focal_length(),
+         }
+    }
+}
+
+// A submitted frame whose pixels are read back from the device without blocking.
+// The device writes into pixels until read_event completes, so it must not be touched or freed before that.
+struct PendingFrame {
+    pixels: Vec<u8>,
+    read_event: Event,
+    upload_events: EventList,
+    kernel_events: EventList,
+    rays_traced: u64,
+    objects_uploaded: usize,
+}
+
+impl PendingFrame {
+    // Only valid once read_event completed, which on the in order queue means every other event did as well.
+    fn get_stats(&self) -> FrameStats {
+        FrameStats {
+            upload_time: profiled_time_of_all(&self.upload_events),
+            kernel_time: profiled_time_of_all(&self.kernel_events),
+            readback_time: profiled_time(&self.read_event),
+            rays_traced: self.rays_traced,
+            objects_uploaded: self.objects_uploaded,
+        }
+    }
+}
+
+impl Drop for PendingFrame {
+    fn drop(&mut self) {
+        let _ = self.read_event.wait_for();
+    }
+}
+
+pub struct OpenCLBackend {
+    width: u32,
+    height: u32,
+    // Part of the frame this backend renders, the whole frame when None.
+    region: Option<Tile>,
+    device_selector: DeviceSelector,
+    kernel_source: KernelSource,
+    kernel_watcher: Option<KernelWatcher>,
+    pro_que: Option<ProQue>,
+    // One per frame in flight, so a frame can be tone mapped while the previous one is still being read.
+    output_buffers: Vec<Buffer<u8>>,
+    next_output_buffer: usize,
+    pending_frames: VecDeque<PendingFrame>,
+    hdr_buffer: Option<Buffer<f32>>,
+    aovs_enabled: bool,
+    // AOV_LEN floats per pixel of the region, only allocated while AOVs are enabled.
+    aov_buffer: Option<Buffer<f32>>,
+    accumulation: Accumulation,
+    anti_aliasing: AntiAliasing,
+    tone_mapping: ToneMapping,
+    stereo: Option<StereoRig>,
+    scene_buffers: Option<SceneBuffers>,
+    camera_buffer: Option<Buffer<f32>>,
+    directionlight_direction_buffer: Option<Buffer<f32>>,
+    directionlight_color_buffer: Option<Buffer<u8>>,
+    uploaded_camera: Vec<f32>,
+    uploaded_directionlight_direction: Vec<f32>,
+    uploaded_directionlight_color: Vec<u8>,
+}
+
+impl OpenCLBackend {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::new_with_device(width, height, DeviceSelector::default())
+    }
+
+    pub fn new_with_device(width: u32, height: u32, device_selector: DeviceSelector) -> Self {
+        Self::new_with_kernel_source(width, height, device_selector, KernelSource::default())
+    }
+
+    pub fn new_with_kernel_source(width: u32, height: u32, device_selector: DeviceSelector, kernel_source: KernelSource) -> Self {
+        Self {
+            width,
+            height,
+            region: None,
+            device_selector,
+            kernel_source,
+            kernel_watcher: None,
+            pro_que: None,
+            output_buffers: Vec::new(),
+            next_output_buffer: 0,
+            pending_frames: VecDeque::new(),
+            hdr_buffer: None,
+            aovs_enabled: false,
+            aov_buffer: None,
+            accumulation: Accumulation::new(),
+            anti_aliasing: AntiAliasing::default(),
+            tone_mapping: ToneMapping::default(),
+            stereo: None,
+            scene_buffers: None,
+            camera_buffer: None,
+            directionlight_direction_buffer: None,
+            directionlight_color_buffer: None,
+            uploaded_camera: Vec::new(),
+            uploaded_directionlight_direction: Vec::new(),
+            uploaded_directionlight_color: Vec::new(),
+         }
+    }
+
+    pub fn get_region(&self) -> Tile {
+        self.region.unwrap_or(Tile::new(0, 0, self.width, self.height))
+    }
+
+    // Restricts submit_frame to region of the frame, and its frames to the pixels of that region.
+    // Frames that are already in flight keep the region they were submitted with.
+    pub fn set_region(&mut self, region: Option<Tile>) {
+        if region == self.region {
+            return;
+        }
+        self.region = region;
+        self.output_buffers.clear();
+        self.hdr_buffer = None;
+        self.aov_buffer = None;
+    }
+
+    // Drops the frame submitted last without reading it, after the device finished writing it.
+    // Its sample still went into the accumulation buffer.
+    pub fn discard_last_frame(&mut self) {
+        self.pending_frames.pop_back();
+    }
+
+    // Loads the kernel source and starts watching the files it came from, if they are on disk.
+    fn load_kernel_source(&mut self) -> Result<String, RendererError> {
+        let (source, paths) = self.kernel_source.load()?;
+        self.kernel_watcher = match self.kernel_source {
+            KernelSource::DIRECTORY(_) => Some(KernelWatcher::new(paths)),
+            KernelSource::EMBEDDED => None,
+        };
+        Ok(source)
+    }
+
+    // Rebuilds the program when one of the watched kernel files changed. When the new source doesn't build,
+    // the build log is printed and the previous program keeps rendering.
+    fn reload_changed_kernels(&mut self) {
+        match self.kernel_watcher.as_ref() {
+            Some(kernel_watcher) if kernel_watcher.has_changed() => (),
+            _ => return,
+        }
+        let pro_que = match self.pro_que.as_ref() {
+            Some(pro_que) => pro_que.clone(),
+            None => return,
+        };
+        let program = self.load_kernel_source().and_then(|source| {
+            Program::builder().src(source).devices(pro_que.device()).build(pro_que.context())
+                .map_err(log_build_error)
+        });
+        match program {
+            Ok(program) => {
+                log::info!("Reloaded the OpenCL kernels");
+                self.pro_que = Some(ProQue::new(pro_que.context().clone(), pro_que.queue().clone(), program, None::<usize>));
+                self.accumulation.reset();
+            }
+            Err(err) => log::error!("Keeping the previous kernels: {}", err),
+        }
+    }
+
+    fn create_buffer<T: OclPrm>(&mut self, len: usize) -> Result<Buffer<T>, RendererError> {
+        Buffer::builder().queue(self.pro_que.as_mut().ok_or(RendererError::RendererNotInitializedError)?.queue().clone())
+            .flags(MemFlags::new().read_only())
+            .len(len)
+            .build().map_err(RendererError::CreateBufferError)
+    }
+
+    fn create_read_write_buffer<T: OclPrm>(&self, len: usize) -> Result<Buffer<T>, RendererError> {
+        Buffer::builder().queue(self.pro_que.as_ref().ok_or(RendererError::RendererNotInitializedError)?.queue().clone())
+            .flags(MemFlags::new().read_write())
+            .len(len)
+            .build().map_err(RendererError::CreateBufferError)
+    }
+
+    // The full frame buffers are only allocated once submit_frame needs them, so renders that only
+    // go through render_tiles never hold the whole image on the device.
+    fn ensure_frame_buffers(&mut self) -> Result<(), RendererError> {
+        let region = self.get_region();
+        if self.aovs_enabled && self.aov_buffer.is_none() {
+            let aov_len = region.get_pixel_count().checked_mul(AOV_LEN).ok_or(RendererError::DimensionsTooBigError)?;
+            self.aov_buffer = Some(self.create_read_write_buffer::<f32>(aov_len)?);
+            self.accumulation.reset();
+        }
+        if !self.output_buffers.is_empty() && self.hdr_buffer.is_some() {
+            return Ok(());
+        }
+        let frame_len = (region.width as usize).checked_mul(region.height as usize)
+            .and_then(|pixels| pixels.checked_mul(4))
+            .ok_or(RendererError::DimensionsTooBigError)?;
+        self.output_buffers.clear();
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+            let output_buffer = self.create_read_write_buffer::<u8>(frame_len)?;
+            self.output_buffers.push(output_buffer);
+        }
+        self.next_output_buffer = 0;
+        self.hdr_buffer = Some(self.create_read_write_buffer::<f32>(frame_len)?);
+        self.accumulation.reset();
+        Ok(())
+    }
+
+    // Enqueues the render kernel for the pixels of tile, using the scene that was last uploaded.
+    fn enqueue_render(&self, hdr_buffer: &Buffer<f32>, aov_buffer: Option<&Buffer<f32>>, sample_index: u32, tile: &Tile, kernel_camera: &KernelCamera, events: &mut EventList) -> Result<(), RendererError> {
+        let scene_buffers = self.scene_buffers.as_ref().ok_or(RendererError::RendererNotInitializedError)?;
+        let kernel = self.pro_que.as_ref().ok_or(RendererError::RendererNotInitializedError)?.kernel_builder("render")
+            .global_work_size(tile.get_pixel_count())
+            .arg(hdr_buffer)
+            .arg(aov_buffer)
+            .arg(sample_index)
+            .arg(self.anti_aliasing.samples_per_pixel)
+            .arg(self.anti_aliasing.pattern as u8)
+            .arg(self.anti_aliasing.filter as u8)
+            .arg(self.width)
+            .arg(self.height)
+            .arg(tile.x)
+            .arg(tile.y)
+            .arg(tile.width)
+            .arg(self.camera_buffer.as_ref().ok_or(RendererError::RendererNotInitializedError)?)
+            .arg(kernel_camera.stereo_layout)
+            .arg(kernel_camera.camera_width)
+            .arg(kernel_camera.camera_height)
+            .arg(kernel_camera.focal_length)
+            .arg(&scene_buffers.cframe_buffer)
+            .arg(&scene_buffers.end_cframe_buffer)
+            .arg(&scene_buffers.render_type_buffer)
+            .arg(scene_buffers.uploaded.object_amnt)
+            .arg(&scene_buffers.object_prop_buffer)
+            .arg(&scene_buffers.prop_offset_buffer)
+            .arg(&scene_buffers.object_uint_prop_buffer)
+            .arg(&scene_buffers.uint_prop_offset_buffer)
+            .arg(&scene_buffers.color_buffer)
+            .arg(self.directionlight_direction_buffer.as_ref().ok_or(RendererError::RendererNotInitializedError)?)
+            .arg(self.directionlight_color_buffer.as_ref().ok_or(RendererError::RendererNotInitializedError)?)
+            .build().map_err(RendererError::AddArgumentsError)?;
+
+        unsafe { kernel.cmd().enew(&mut *events).enq().map_err(RendererError::ExecuteKernelError)?; }
+        Ok(())
+    }
+
+    // Enqueues the tone mapping of the first pixel_count pixels of hdr_buffer into output_buffer.
+    fn enqueue_tone_map(&self, hdr_buffer: &Buffer<f32>, output_buffer: &Buffer<u8>, pixel_count: usize, events: &mut EventList) -> Result<(), RendererError> {
+        let kernel = self.pro_que.as_ref().ok_or(RendererError::RendererNotInitializedError)?.kernel_builder("tone_map")
+            .global_work_size(pixel_count)
+            .arg(hdr_buffer)
+            .arg(output_buffer)
+            .arg(self.tone_mapping.operator as u8)
+            .arg(self.tone_mapping.exposure)
+            .build().map_err(RendererError::AddArgumentsError)?;
+
+        unsafe { kernel.cmd().enew(&mut *events).enq().map_err(RendererError::ExecuteKernelError)?; }
+        Ok(())
+    }
+
+    // Brings the device copy of the scene up to date. Returns the amount of objects that had to be uploaded,
+    // and whether the camera, an object or a light changed since the last upload.
+    fn upload_scene(&mut self, camera_vec: Vec<f32>, scene: PackedScene, directionlight_direction: Vec<f32>, directionlight_color: Vec<u8>, events: &mut EventList) -> Result<(usize, bool), RendererError> {
+        let needs_realloc = match self.scene_buffers.as_ref() {
+            Some(scene_buffers) => !scene_buffers.fits(&scene),
+            None => true,
+        };
+        if needs_realloc {
+            let queue = self.pro_que.as_ref().ok_or(RendererError::RendererNotInitializedError)?.queue().clone();
+            self.scene_buffers = Some(SceneBuffers::new(&queue, scene_capacity_for(scene.object_amnt as usize), scene_capacity_for(scene.object_props.len()), scene_capacity_for(scene.object_uint_props.len()))?);
+        }
+        let (uploaded_amnt, mut changed) = self.scene_buffers.as_mut().ok_or(RendererError::RendererNotInitializedError)?.upload(scene, &mut *events)?;
+
+        if camera_vec != self.uploaded_camera {
+            changed = true;
+            self.camera_buffer.as_ref().ok_or(RendererError::RendererNotInitializedError)?.write(&camera_vec).enew(&mut *events).enq()
+                .map_err(RendererError::WriteBufferError)?;
+            self.uploaded_camera = camera_vec;
+        }
+        if directionlight_direction != self.uploaded_directionlight_direction {
+            changed = true;
+            self.directionlight_direction_buffer.as_ref().ok_or(RendererError::RendererNotInitializedError)?.write(&directionlight_direction).enew(&mut *events).enq()
+                .map_err(RendererError::WriteBufferError)?;
+            self.uploaded_directionlight_direction = directionlight_direction;
+        }
+        if directionlight_color != self.uploaded_directionlight_color {
+            changed = true;
+            self.directionlight_color_buffer.as_ref().ok_or(RendererError::RendererNotInitializedError)?.write(&directionlight_color).enew(&mut *events).enq()
+                .map_err(RendererError::WriteBufferError)?;
+            self.uploaded_directionlight_color = directionlight_color;
+        }
+        Ok((uploaded_amnt, changed))
+    }
+
+    // Renders tiles from queue until it runs out, with the scene uploaded once into the resident scene buffers.
+    // Several devices can share a queue, each taking the next tile once it's done with its last one.
+    pub fn render_tile_queue(&mut self, mut camera: Camera, mut render_objects: Vec<RenderObject>, directionlight_direction: Vec<f32>, directionlight_color: Vec<u8>, queue: &TileQueue, on_tile: &mut TileCallback) -> Result<(), RendererError> {
+        let max_tile_len = queue.get_max_pixel_count() * 4;
+        if max_tile_len == 0 {
+            return Ok(());
+        }
+        let scene = PackedScene::new(&mut render_objects);
+        let (camera_vec, stereo_layout) = stereo_camera_vec(&mut camera, &self.stereo);
+        let (_, changed) = self.upload_scene(camera_vec, scene, directionlight_direction, directionlight_color, &mut EventList::new())?;
+        // The accumulated image is of what was uploaded before.
+        if changed {
+            self.accumulation.reset();
+        }
+
+        let kernel_camera = KernelCamera::new(&mut camera, stereo_layout, self.width, self.height);
+        // One set of tile sized buffers is reused for every tile and freed again afterwards.
+        let tile_output_buffer = self.create_read_write_buffer::<u8>(max_tile_len)?;
+        let tile_hdr_buffer = self.create_read_write_buffer::<f32>(max_tile_len)?;
+        let tile_aov_buffer = if self.aovs_enabled { Some(self.create_read_write_buffer::<f32>(max_tile_len / 4 * AOV_LEN)?) } else { None };
+        let mut pixels = vec![0u8; max_tile_len];
+        let mut aovs = vec![0f32; if self.aovs_enabled { max_tile_len / 4 * AOV_LEN } else { 0 }];
+        while let Some(tile) = queue.next() {
+            let mut tile_events = EventList::new();
+            self.enqueue_render(&tile_hdr_buffer, tile_aov_buffer.as_ref(), 0, &tile, &kernel_camera, &mut tile_events)?;
+            self.enqueue_tone_map(&tile_hdr_buffer, &tile_output_buffer, tile.get_pixel_count(), &mut tile_events)?;
+            let tile_pixels = &mut pixels[..tile.get_pixel_count() * 4];
+            tile_output_buffer.read(&mut *tile_pixels).enq().map_err(RendererError::ReadBufferError)?;
+            let tile_aovs = match tile_aov_buffer.as_ref() {
+                Some(tile_aov_buffer) => {
+                    let tile_aovs = &mut aovs[..tile.get_pixel_count() * AOV_LEN];
+                    tile_aov_buffer.read(&mut *tile_aovs).enq().map_err(RendererError::ReadBufferError)?;
+                    Some(&*tile_aovs)
+                }
+                None => None,
+            };
+            on_tile(&tile, tile_pixels, tile_aovs);
+        }
+        Ok(())
+    }
+}
+
+impl RenderBackend for OpenCLBackend {
+    fn init(&mut self) -> Result<(), RendererError> {
+        let mut builder = ProQue::builder();
+        builder.src(self.load_kernel_source()?);
+        // Profiling is cheap enough to leave on, it's what FrameStats are measured with.
+        builder.queue_properties(CommandQueueProperties::new().profiling());
+        if !self.device_selector.is_default() {
+            let (platform, device) = self.device_selector.select()?;
+            builder.platform(platform).device(device);
+        }
+        let pro_que = builder.build().map_err(log_build_error)?;
+        log::info!("Using OpenCL device {}", pro_que.device().name().unwrap_or_default());
+        self.pending_frames.clear();
+        self.pro_que = Some(pro_que);
+        
+        self.output_buffers.clear();
+        self.hdr_buffer = None;
+        self.aov_buffer = None;
+        self.accumulation.reset();
+        // Room for both eyes of a stereo rig.
+        self.camera_buffer = Some(self.create_buffer::<f32>(2 * CAMERA_VEC_LEN)?);
+        self.directionlight_direction_buffer = Some(self.create_buffer::<f32>(3)?);
+        self.directionlight_color_buffer = Some(self.create_buffer::<u8>(3)?);
+        // Fresh buffers hold nothing yet, so everything has to be uploaded on the first frame.
+        self.scene_buffers = None;
+        self.uploaded_camera.clear();
+        self.uploaded_directionlight_direction.clear();
+        self.uploaded_directionlight_color.clear();
+        
+        Ok(())
+    }
+
+    fn submit_frame(&mut self, mut camera: Camera, mut render_objects: Vec<RenderObject>, directionlight_direction: Vec<f32>, directionlight_color: Vec<u8>) -> Result<(), RendererError> {
+        if self.pending_frames.len() >= MAX_FRAMES_IN_FLIGHT {
+            return Err(RendererError::FramesInFlightError);
+        }
+        self.reload_changed_kernels();
+        self.ensure_frame_buffers()?;
+
+        let scene = PackedScene::new(&mut render_objects);
+        let (camera_vec, stereo_layout) = stereo_camera_vec(&mut camera, &self.stereo);
+        let hdr_buffer = self.hdr_buffer.clone().ok_or(RendererError::RendererNotInitializedError)?;
+        let output_buffer = self.output_buffers[self.next_output_buffer].clone();
+        self.next_output_buffer = (self.next_output_buffer + 1) % self.output_buffers.len();
+        let frame = self.get_region();
+        let mut upload_events = EventList::new();
+        let mut kernel_events = EventList::new();
+        let mut rays_traced = 0;
+        // Only what changed since the last frame is uploaded, and a change starts the accumulation over.
+        let (objects_uploaded, scene_changed) = self.upload_scene(camera_vec, scene, directionlight_direction, directionlight_color, &mut upload_events)?;
+        // A converged image isn't rendered again, but is still tone mapped in case the tone mapping changed.
+        if let Some(sample_index) = self.accumulation.next_sample(scene_changed) {
+            let kernel_camera = KernelCamera::new(&mut camera, stereo_layout, self.width, self.height);
+            self.enqueue_render(&hdr_buffer, self.aov_buffer.as_ref(), sample_index, &frame, &kernel_camera, &mut kernel_events)?;
+            rays_traced = (frame.get_pixel_count() as u64) * (self.anti_aliasing.samples_per_pixel as u64);
+            if stereo_layout == StereoLayout::ANAGLYPH as u8 {
+                rays_traced *= 2;
+            }
+        }
+        self.enqueue_tone_map(&hdr_buffer, &output_buffer, frame.get_pixel_count(), &mut kernel_events)?;
+
+        let mut pixels = vec![0u8; output_buffer.len()];
+        let mut read_event = Event::empty();
+        unsafe {
+            output_buffer.read(&mut pixels).block(false).enew(&mut read_event).enq()
+                .map_err(RendererError::ReadBufferError)?;
+        }
+        self.pending_frames.push_back(PendingFrame {
+            pixels,
+            read_event,
+            upload_events,
+            kernel_events,
+            rays_traced,
+            objects_uploaded,
+        });
+        // Nothing has to wait for this frame yet, so make sure the device starts on it right away.
+        self.pro_que.as_ref().ok_or(RendererError::RendererNotInitializedError)?.queue().flush()
+            .map_err(RendererError::ExecuteKernelError)?;
+        Ok(())
+    }
+
+    fn wait_frame(&mut self) -> Result<Option<(Vec::<u8>, FrameStats)>, RendererError> {
+        let mut frame = match self.pending_frames.pop_front() {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        frame.read_event.wait_for().map_err(|e| RendererError::ReadBufferError(e.into()))?;
+        let stats = frame.get_stats();
+        Ok(Some((std::mem::take(&mut frame.pixels), stats)))
+    }
+
+    fn get_frames_in_flight(&self) -> usize {
+        self.pending_frames.len()
+    }
+
+    fn render_tiles(&mut self, camera: Camera, render_objects: Vec<RenderObject>, directionlight_direction: Vec<f32>, directionlight_color: Vec<u8>, tiles: &[Tile], on_tile: &mut TileCallback) -> Result<(), RendererError> {
+        self.render_tile_queue(camera, render_objects, directionlight_direction, directionlight_color, &TileQueue::new(tiles), on_tile)
+    }
+
+    fn resize(&mut self, width: u32, height: u32) -> Result<(), RendererError> {
+        self.width = width;
+        self.height = height;
+        self.region = None;
+        // Frames of the old size are of no use anymore.
+        self.pending_frames.clear();
+        self.output_buffers.clear();
+        self.hdr_buffer = None;
+        self.aov_buffer = None;
+        if self.pro_que.is_some() {
+            self.ensure_frame_buffers()?;
+        }
+        Ok(())
+    }
+
+    fn read_hdr_frame(&mut self) -> Result<Vec::<f32>, RendererError> {
+        let hdr_buffer = self.hdr_buffer.as_ref().ok_or(RendererError::RendererNotInitializedError)?;
+        let mut vec = vec![0f32; hdr_buffer.len()];
+        hdr_buffer.read(&mut vec).enq().map_err(RendererError::ReadBufferError)?;
+        Ok(vec)
+    }
+
+    fn read_aov_frame(&mut self) -> Result<Vec::<f32>, RendererError> {
+        if !self.aovs_enabled {
+            return Err(RendererError::AovsDisabledError);
+        }
+        let aov_buffer = self.aov_buffer.as_ref().ok_or(RendererError::RendererNotInitializedError)?;
+        let mut vec = vec![0f32; aov_buffer.len()];
+        aov_buffer.read(&mut vec).enq().map_err(RendererError::ReadBufferError)?;
+        Ok(vec)
+    }
+
+    fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.tone_mapping = tone_mapping;
+    }
+
+    fn set_accumulation(&mut self, enabled: bool) {
+        self.accumulation.set_enabled(enabled);
+    }
+
+    fn reset_accumulation(&mut self) {
+        self.accumulation.reset();
+    }
+
+    fn get_accumulated_samples(&self) -> u32 {
+        self.accumulation.get_sample_count()
+    }
+
+    fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) {
+        self.anti_aliasing = anti_aliasing;
+        self.accumulation.reset();
+    }
+
+    fn set_stereo(&mut self, stereo: Option<StereoRig>) {
+        self.stereo = stereo;
+        self.accumulation.reset();
+    }
+
+    fn set_aovs(&mut self, enabled: bool) {
+        self.aovs_enabled = enabled;
+        // Allocated on the next frame, which starts the accumulation over as the buffer has no history.
+        self.aov_buffer = None;
+    }
+}
@@ -1,10 +1,72 @@
+use std::str::FromStr;
 use crate::engine::cframe::CFrame;
+use crate::engine::world::World;
+use crate::engine::render::PackedScene;
+use crate::engine::backends::cpu::cast_primary_ray;
+
+// Amount of floats in Camera::to_vec, which is the layout the render kernel reads the camera from.
+pub const CAMERA_VEC_LEN: usize = 30;
+
+// How the rays leave the camera.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub enum Projection {
+    // Pinhole camera, rays spread out from the camera position through the image plane.
+    #[default]
+    PERSPECTIVE = 0,
+    // Parallel rays, so objects keep their size at any distance.
+    ORTHOGRAPHIC = 1,
+    // Full 360 by 180 degree panorama, longitude to the right and latitude up. Meant for 2:1 frames.
+    EQUIRECTANGULAR = 2,
+    // Fisheye where the distance from the centre of the image grows linearly with the angle, up to fov / 2.
+    EQUIDISTANT = 3,
+    // Fisheye that keeps the area of everything the same, like most real fisheye lenses.
+    EQUISOLID = 4,
+}
+
+// Which side of the image the field of view spans, the other side follows from the aspect ratio of the frame.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub enum FovAxis {
+    #[default]
+    HORIZONTAL,
+    VERTICAL,
+}
 
 #[derive(Default, Copy, Clone)]
 pub struct Camera {
+    // In degrees.
     fov: f32,
+    fov_axis: FovAxis,
     focal_length: f32,
+    projection: Projection,
+    // Height of the image in world units for the orthographic projection.
+    view_height: f32,
+    // Radius of the lens in world units, everything is in focus when it's 0.
+    aperture_radius: f32,
+    // Distance in front of the camera of the plane that is in focus.
+    focus_distance: f32,
+    // Corners of the aperture, which is what gives out of focus highlights their shape. Below 3 it's a disk.
+    aperture_blades: u32,
+    // Horizontal shift of the perspective image plane relative to the focal length, which moves the image
+    // sideways without turning the camera.
+    lens_shift: f32,
     pub cframe: CFrame,
+    // Where the camera is when the shutter closes, None when it stands still.
+    end_cframe: Option<CFrame>,
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "perspective" | "pinhole" => Ok(Projection::PERSPECTIVE),
+            "orthographic" | "ortho" => Ok(Projection::ORTHOGRAPHIC),
+            "equirectangular" | "360" => Ok(Projection::EQUIRECTANGULAR),
+            "fisheye" | "equidistant" => Ok(Projection::EQUIDISTANT),
+            "equisolid" => Ok(Projection::EQUISOLID),
+            _ => Err(format!("Unknown projection '{}', expected 'perspective', 'orthographic', 'equirectangular', 'fisheye' or 'equisolid'", s)),
+        }
+    }
 }
 
 impl Camera {
@@ -12,6 +74,20 @@ impl Camera {
         Self {
             fov,
             focal_length,
+            view_height: 50f32,
+            focus_distance: 50f32,
+            ..Default::default()
+         }
+    }
+
+    // Orthographic camera showing view_height world units from the bottom to the top of the image.
+    pub fn new_orthographic(view_height: f32) -> Self {
+        Self {
+            fov: 90f32,
+            focal_length: 0.1f32,
+            projection: Projection::ORTHOGRAPHIC,
+            view_height,
+            focus_distance: 50f32,
             ..Default::default()
          }
     }
@@ -19,14 +95,123 @@ impl Camera {
     pub fn to_vec(&mut self) -> Vec<f32> {
         let mut vec = self.cframe.to_vec();
         vec.push(self.fov);
-        return vec;
+        vec.push(self.projection as u8 as f32);
+        vec.push(self.aperture_radius);
+        vec.push(self.focus_distance);
+        vec.push(self.aperture_blades as f32);
+        vec.push(self.lens_shift);
+        vec.extend(self.end_cframe.unwrap_or(self.cframe).to_vec());
+        vec
     }
 
     pub fn get_fov(&mut self) -> f32 {
-        return self.fov;
+        self.fov
+    }
+
+    pub fn get_fov_axis(&mut self) -> FovAxis {
+        self.fov_axis
+    }
+
+    // Field of view in degrees across the given side of the image. Below 180 for the perspective projection,
+    // the fisheyes go up to 360.
+    pub fn set_fov(&mut self, fov: f32, fov_axis: FovAxis) {
+        self.fov = fov;
+        self.fov_axis = fov_axis;
     }
 
     pub fn get_focal_length(&mut self) -> f32 {
-        return self.focal_length;
+        self.focal_length
+    }
+
+    pub fn get_projection(&mut self) -> Projection {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    pub fn get_view_height(&mut self) -> f32 {
+        self.view_height
+    }
+
+    pub fn set_view_height(&mut self, view_height: f32) {
+        self.view_height = view_height;
+    }
+
+    pub fn get_aperture_radius(&mut self) -> f32 {
+        self.aperture_radius
+    }
+
+    pub fn set_aperture_radius(&mut self, aperture_radius: f32) {
+        self.aperture_radius = aperture_radius.max(0f32);
+    }
+
+    pub fn get_focus_distance(&mut self) -> f32 {
+        self.focus_distance
+    }
+
+    pub fn set_focus_distance(&mut self, focus_distance: f32) {
+        self.focus_distance = focus_distance;
+    }
+
+    pub fn get_aperture_blades(&mut self) -> u32 {
+        self.aperture_blades
+    }
+
+    pub fn set_aperture_blades(&mut self, aperture_blades: u32) {
+        self.aperture_blades = aperture_blades;
+    }
+
+    pub fn get_lens_shift(&mut self) -> f32 {
+        self.lens_shift
+    }
+
+    pub fn set_lens_shift(&mut self, lens_shift: f32) {
+        self.lens_shift = lens_shift;
+    }
+
+    pub fn get_end_cframe(&mut self) -> Option<CFrame> {
+        self.end_cframe
+    }
+
+    // The camera moves from cframe to end_cframe while the shutter is open, blurring everything that moves
+    // relative to it.
+    pub fn set_end_cframe(&mut self, end_cframe: Option<CFrame>) {
+        self.end_cframe = end_cframe;
+    }
+
+    // Focuses on whatever is visible at pixel (x, y) of a width x height frame of world.
+    // Returns the new focus distance, or None and keeps the old one when the pixel doesn't show any object.
+    pub fn autofocus(&mut self, world: &mut World, x: u32, y: u32, width: u32, height: u32) -> Option<f32> {
+        let scene = PackedScene::new(&mut world.get_render_objects());
+        let hit = cast_primary_ray(self, &None, &scene, x as f32 + 0.5, y as f32 + 0.5, width, height)?;
+        self.focus_distance = hit.depth;
+        Some(hit.depth)
+    }
+
+    // Size of the image plane for an output of width x height pixels. For the perspective projection the
+    // plane is at focal_length in front of the camera, the orthographic one is at the camera itself.
+    // The panoramic projections span angles instead, in radians.
+    pub fn get_camera_dimensions(&mut self, width: u32, height: u32) -> (f32, f32) {
+        let aspect_ratio = (width as f32) / (height as f32);
+        let extent = match self.projection {
+            Projection::ORTHOGRAPHIC => return (self.view_height * aspect_ratio, self.view_height),
+            Projection::EQUIRECTANGULAR => return (2f32 * std::f32::consts::PI, std::f32::consts::PI),
+            Projection::PERSPECTIVE => 2f32 * self.focal_length * (self.fov.to_radians() / 2f32).tan(),
+            Projection::EQUIDISTANT | Projection::EQUISOLID => self.fov.to_radians(),
+        };
+        match self.fov_axis {
+            FovAxis::HORIZONTAL => (extent, extent / aspect_ratio),
+            FovAxis::VERTICAL => (extent * aspect_ratio, extent),
+        }
+    }
+
+    // Largest field of view in degrees the projection can show.
+    pub fn get_max_fov(&mut self) -> f32 {
+        match self.projection {
+            Projection::PERSPECTIVE => 179f32,
+            _ => 360f32,
+        }
     }
 }
\ No newline at end of file
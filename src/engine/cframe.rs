@@ -31,7 +31,24 @@ impl CFrame {
     }
 
     pub fn to_vec(&mut self) -> Vec<f32> {
-        return vec![self.x, self.y, self.z, self.r00, self.r01, self.r02, self.r10, self.r11, self.r12, self.r20, self.r21, self.r22];
+        vec![self.x, self.y, self.z, self.r00, self.r01, self.r02, self.r10, self.r11, self.r12, self.r20, self.r21, self.r22]
+    }
+
+    // CFrame at time, between 0 for self and 1 for end. Same as interpolate_cframe in the render kernel.
+    pub fn interpolate(&self, end: &CFrame, time: f32) -> CFrame {
+        let lerp = |a: f32, b: f32| a + (b - a) * time;
+        let normalize = |v: [f32; 3]| {
+            let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+            [v[0] / length, v[1] / length, v[2] / length]
+        };
+        let row0 = normalize([lerp(self.r00, end.r00), lerp(self.r01, end.r01), lerp(self.r02, end.r02)]);
+        let row1 = [lerp(self.r10, end.r10), lerp(self.r11, end.r11), lerp(self.r12, end.r12)];
+        let projection = row1[0] * row0[0] + row1[1] * row0[1] + row1[2] * row0[2];
+        let row1 = normalize([row1[0] - projection * row0[0], row1[1] - projection * row0[1], row1[2] - projection * row0[2]]);
+        CFrame::new(lerp(self.x, end.x), lerp(self.y, end.y), lerp(self.z, end.z),
+                           row0[0], row0[1], row0[2],
+                           row1[0], row1[1], row1[2],
+                           row0[1] * row1[2] - row0[2] * row1[1], row0[2] * row1[0] - row0[0] * row1[2], row0[0] * row1[1] - row0[1] * row1[0])
     }
 
     pub fn multiply_vector(&mut self, x: f32, y: f32, z: f32) {
@@ -93,4 +110,7 @@ impl Default for CFrame {
 pub trait Positionable {
     fn set_cframe(&mut self, cframe: CFrame);
     fn set_position(&mut self, x: f32, y: f32, z: f32);
+    // Where the object is when the shutter closes, it moves there from its cframe during every frame.
+    // None keeps it standing still.
+    fn set_end_cframe(&mut self, end_cframe: Option<CFrame>);
 }
\ No newline at end of file
@@ -0,0 +1,63 @@
+use crate::engine::cframe::{CFrame, Positionable};
+use crate::engine::render::{Renderable, RenderObject, RenderType, Intersection};
+use crate::engine::backends::cpu::{intersect_cuboid, cuboid_normal, ray_cframe};
+
+// Box around the position of its cframe, turned along with its rotation.
+#[derive(Default)]
+pub struct Cuboid {
+    cframe: CFrame,
+    end_cframe: Option<CFrame>,
+    // Half of the width, height and depth, along the right, up and back vectors of the cframe.
+    half_size: [f32; 3],
+    color: Vec<u8>,
+}
+
+impl Cuboid {
+    pub fn new(half_width: f32, half_height: f32, half_depth: f32) -> Self {
+        Self {
+            half_size: [half_width, half_height, half_depth],
+            ..Default::default()
+         }
+    }
+}
+
+impl Renderable for Cuboid {
+    fn get_render_object(&mut self) -> RenderObject {
+        let mut render_object = RenderObject::new(self.cframe, RenderType::CUBOID, self.half_size.to_vec(), self.color.clone());
+        if let Some(end_cframe) = self.end_cframe {
+            render_object.set_end_cframe(end_cframe);
+        }
+        render_object
+    }
+
+    fn set_color(&mut self, red: u8, green: u8, blue: u8) {
+        self.color = vec![red, green, blue];
+    }
+
+    fn intersect(&mut self, origin: &[f32; 3], direction: &[f32; 3]) -> Option<Intersection> {
+        let cframe = self.cframe.to_vec();
+        let t = intersect_cuboid(&cframe, &self.half_size, &ray_cframe(origin, direction));
+        if t <= 0f32 {
+            return None;
+        }
+        let position = [origin[0] + direction[0] * t, origin[1] + direction[1] * t, origin[2] + direction[2] * t];
+        Some(Intersection {
+            distance: t,
+            normal: cuboid_normal(&cframe, &self.half_size, &position),
+        })
+    }
+}
+
+impl Positionable for Cuboid {
+    fn set_cframe(&mut self, cframe: CFrame) {
+        self.cframe = cframe;
+    }
+
+    fn set_position(&mut self, x: f32, y: f32, z: f32) {
+        self.cframe = CFrame::new(x, y, z, self.cframe.r00, self.cframe.r01, self.cframe.r02, self.cframe.r10, self.cframe.r11, self.cframe.r12, self.cframe.r20, self.cframe.r21, self.cframe.r22);
+    }
+
+    fn set_end_cframe(&mut self, end_cframe: Option<CFrame>) {
+        self.end_cframe = end_cframe;
+    }
+}
\ No newline at end of file
@@ -3,10 +3,24 @@ use ocl::Error as OclError;
 
 #[derive(Error, Debug)]
 pub enum RendererError {
-    #[error("Renderer not initialized!")]
+    #[error("Size too big, the frame doesn't fit in memory!")]
     DimensionsTooBigError,
-    #[error("Size too big, dimensions don't fit in u16!")]
+    #[error("Size too small, the frame needs at least one pixel!")]
+    DimensionsTooSmallError,
+    #[error("Renderer not initialized!")]
     RendererNotInitializedError,
+    #[error("Too many frames in flight, wait for one first!")]
+    FramesInFlightError,
+    #[error("No OpenCL platform matches '{0}'!")]
+    PlatformNotFoundError(String),
+    #[error("No OpenCL device matches '{0}'!")]
+    DeviceNotFoundError(String),
+    #[error("Failed to query OpenCL device!")]
+    DeviceQueryError(OclError),
+    #[error("No kernel source named '{0}'!")]
+    KernelNotFoundError(String),
+    #[error("Failed to read kernel source '{0}'!")]
+    KernelReadError(String, #[source] std::io::Error),
     #[error("Failed to build kernel!")]
     KernelBuildError(OclError),
     #[error("Failed to create buffer!")]
@@ -15,8 +29,35 @@ pub enum RendererError {
     AddArgumentsError(OclError),
     #[error("Failed to execute kernel!")]
     ExecuteKernelError(OclError),
+    #[error("Failed to write buffer!")]
+    WriteBufferError(OclError),
     #[error("Failed to read buffer!")]
     ReadBufferError(OclError),
+    #[error("AOVs aren't enabled, call set_aovs first!")]
+    AovsDisabledError,
     #[error("Unknown renderer error!")]
     Unknown,
 }
+
+
+#[derive(Error, Debug)]
+pub enum OutputError {
+    #[error("Failed to render frame!")]
+    RenderError(#[from] RendererError),
+    #[error("Unsupported output format '{0}', expected .png or .ppm!")]
+    UnsupportedFormatError(String),
+    #[error("Frame has {0} bytes, expected {1} for the given dimensions!")]
+    FrameSizeError(usize, usize),
+    #[error("Failed to write output file!")]
+    WriteError(#[from] std::io::Error),
+    #[error("Failed to encode PNG!")]
+    PngEncodingError(#[from] png::EncodingError),
+}
+
+#[derive(Error, Debug)]
+pub enum MeshError {
+    #[error("Triangle {0} uses vertex {1}, but the mesh only has {2} vertices!")]
+    IndexOutOfBoundsError(usize, u32, usize),
+    #[error("Mesh has {0} {1}, expected one for each of its {2} vertices!")]
+    VertexAttributeCountError(usize, &'static str, usize),
+}
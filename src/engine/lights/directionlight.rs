@@ -12,11 +12,11 @@ impl DirectionLight {
     }
 
     pub fn get_direction(&mut self) -> Vec<f32> {
-        return self.direction.clone();
+        self.direction.clone()
     }
 
     pub fn get_color(&mut self) -> Vec<u8> {
-        return self.color.clone();
+        self.color.clone()
     }
 }
 
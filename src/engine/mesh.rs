@@ -0,0 +1,173 @@
+use crate::engine::cframe::{CFrame, Positionable};
+use crate::engine::render::{Renderable, RenderObject, RenderType, Intersection};
+use crate::engine::error::MeshError;
+use crate::engine::backends::cpu::{trace_mesh, mesh_normal, ray_cframe};
+
+// Whole numbers in front of the vertex indices in the uint parameters of a mesh, MESH_HEADER_LEN in mesh.cl.
+pub const MESH_HEADER_LEN: usize = 3;
+// Floats of the bounds in front of the vertices in the parameters of a mesh, MESH_BOUNDS_LEN in mesh.cl.
+pub const MESH_BOUNDS_LEN: usize = 6;
+// Flag in the parameters of a mesh that has vertex normals, MESH_HAS_NORMALS in mesh.cl.
+pub const MESH_HAS_NORMALS: u32 = 1;
+// Flag in the parameters of a mesh that has vertex UVs, MESH_HAS_UVS in mesh.cl.
+pub const MESH_HAS_UVS: u32 = 2;
+
+// Triangle mesh with its vertices relative to its cframe. Every ray is tested against every triangle inside
+// the bounds of the mesh, so it's meant for models of up to a few thousand triangles.
+pub struct Mesh {
+    cframe: CFrame,
+    end_cframe: Option<CFrame>,
+    positions: Vec<[f32; 3]>,
+    // Three vertex indices per triangle.
+    indices: Vec<[u32; 3]>,
+    // One per vertex, blended over every triangle for smooth shading. Without them every triangle is flat.
+    normals: Option<Vec<[f32; 3]>>,
+    // One per vertex, uploaded after the normals for texturing.
+    uvs: Option<Vec<[f32; 2]>>,
+    color: Vec<u8>,
+    // The parameters of the mesh as the kernel reads them, only packed again when the vertices change.
+    props: Vec<f32>,
+    uint_props: Vec<u32>,
+}
+
+impl Mesh {
+    // Fails when a triangle uses a vertex past the end of positions.
+    pub fn new(positions: Vec<[f32; 3]>, indices: Vec<[u32; 3]>) -> Result<Self, MeshError> {
+        for (triangle, triangle_indices) in indices.iter().enumerate() {
+            if let Some(index) = triangle_indices.iter().find(|index| (**index as usize) >= positions.len()) {
+                return Err(MeshError::IndexOutOfBoundsError(triangle, *index, positions.len()));
+            }
+        }
+        let mut mesh = Self {
+            cframe: CFrame::default(),
+            end_cframe: None,
+            positions,
+            indices,
+            normals: None,
+            uvs: None,
+            color: Vec::new(),
+            props: Vec::new(),
+            uint_props: Vec::new(),
+         };
+        mesh.pack_props();
+        Ok(mesh)
+    }
+
+    pub fn get_positions(&mut self) -> &Vec<[f32; 3]> {
+        &self.positions
+    }
+
+    pub fn get_indices(&mut self) -> &Vec<[u32; 3]> {
+        &self.indices
+    }
+
+    pub fn get_normals(&mut self) -> Option<&Vec<[f32; 3]>> {
+        self.normals.as_ref()
+    }
+
+    // Vertex normals, fails unless there is one for every vertex.
+    pub fn set_normals(&mut self, normals: Option<Vec<[f32; 3]>>) -> Result<(), MeshError> {
+        if let Some(normals) = &normals {
+            if normals.len() != self.positions.len() {
+                return Err(MeshError::VertexAttributeCountError(normals.len(), "normals", self.positions.len()));
+            }
+        }
+        self.normals = normals;
+        self.pack_props();
+        Ok(())
+    }
+
+    pub fn get_uvs(&mut self) -> Option<&Vec<[f32; 2]>> {
+        self.uvs.as_ref()
+    }
+
+    // Vertex UVs, fails unless there is one for every vertex.
+    pub fn set_uvs(&mut self, uvs: Option<Vec<[f32; 2]>>) -> Result<(), MeshError> {
+        if let Some(uvs) = &uvs {
+            if uvs.len() != self.positions.len() {
+                return Err(MeshError::VertexAttributeCountError(uvs.len(), "UVs", self.positions.len()));
+            }
+        }
+        self.uvs = uvs;
+        self.pack_props();
+        Ok(())
+    }
+
+    // Packs the parameters the kernel reads the mesh from, see mesh.cl for the layout.
+    fn pack_props(&mut self) {
+        let vertex_count = self.positions.len();
+        let mut bounds = [f32::INFINITY, f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY];
+        for position in self.positions.iter() {
+            for axis in 0..3 {
+                bounds[axis] = bounds[axis].min(position[axis]);
+                bounds[3 + axis] = bounds[3 + axis].max(position[axis]);
+            }
+        }
+        let mut flags = 0;
+        let mut props = Vec::with_capacity(MESH_BOUNDS_LEN + vertex_count * 8);
+        props.extend(bounds);
+        for position in self.positions.iter() {
+            props.extend(position);
+        }
+        if let Some(normals) = &self.normals {
+            flags |= MESH_HAS_NORMALS;
+            for normal in normals.iter() {
+                props.extend(normal);
+            }
+        }
+        if let Some(uvs) = &self.uvs {
+            flags |= MESH_HAS_UVS;
+            for uv in uvs.iter() {
+                props.extend(uv);
+            }
+        }
+        let mut uint_props = Vec::with_capacity(MESH_HEADER_LEN + self.indices.len() * 3);
+        uint_props.push(self.indices.len() as u32);
+        uint_props.push(vertex_count as u32);
+        uint_props.push(flags);
+        for triangle in self.indices.iter() {
+            uint_props.extend(triangle);
+        }
+        self.props = props;
+        self.uint_props = uint_props;
+    }
+}
+
+impl Renderable for Mesh {
+    fn get_render_object(&mut self) -> RenderObject {
+        let mut render_object = RenderObject::new(self.cframe, RenderType::MESH, self.props.clone(), self.color.clone());
+        render_object.set_object_uint_props(self.uint_props.clone());
+        if let Some(end_cframe) = self.end_cframe {
+            render_object.set_end_cframe(end_cframe);
+        }
+        render_object
+    }
+
+    fn set_color(&mut self, red: u8, green: u8, blue: u8) {
+        self.color = vec![red, green, blue];
+    }
+
+    fn intersect(&mut self, origin: &[f32; 3], direction: &[f32; 3]) -> Option<Intersection> {
+        let cframe = self.cframe.to_vec();
+        let ray = ray_cframe(origin, direction);
+        let (triangle, t, weights) = trace_mesh(&cframe, &self.props, &self.uint_props, &ray)?;
+        Some(Intersection {
+            distance: t,
+            normal: mesh_normal(&cframe, &self.props, &self.uint_props, &ray, triangle, &weights),
+        })
+    }
+}
+
+impl Positionable for Mesh {
+    fn set_cframe(&mut self, cframe: CFrame) {
+        self.cframe = cframe;
+    }
+
+    fn set_position(&mut self, x: f32, y: f32, z: f32) {
+        self.cframe = CFrame::new(x, y, z, self.cframe.r00, self.cframe.r01, self.cframe.r02, self.cframe.r10, self.cframe.r11, self.cframe.r12, self.cframe.r20, self.cframe.r21, self.cframe.r22);
+    }
+
+    fn set_end_cframe(&mut self, end_cframe: Option<CFrame>) {
+        self.end_cframe = end_cframe;
+    }
+}
\ No newline at end of file
@@ -1,8 +1,20 @@
 pub mod renderer;
+pub mod backends;
 pub mod error;
 pub mod cframe;
 pub mod camera;
 pub mod sphere;
+pub mod plane;
+pub mod cuboid;
+pub mod mesh;
 pub mod render;
 pub mod world;
-pub mod lights;
\ No newline at end of file
+pub mod output;
+pub mod accumulation;
+pub mod sampling;
+pub mod tiling;
+pub mod tonemapping;
+pub mod lights;
+pub mod stats;
+pub mod stereo;
+pub mod aov;
\ No newline at end of file
@@ -0,0 +1,103 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use crate::engine::error::OutputError;
+use crate::engine::renderer::Renderer;
+use crate::engine::camera::Camera;
+use crate::engine::world::World;
+use crate::engine::tiling::TileProgress;
+use crate::engine::aov::{Aov, AovFrame};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ImageFormat {
+    PNG,
+    PPM,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Result<Self, OutputError> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        match extension.as_str() {
+            "png" => Ok(ImageFormat::PNG),
+            "ppm" => Ok(ImageFormat::PPM),
+            _ => Err(OutputError::UnsupportedFormatError(extension)),
+        }
+    }
+}
+
+// Writes an RGBA frame as returned by Renderer::render_frame to path, the format is picked from the extension.
+pub fn save_frame(path: &Path, width: u32, height: u32, frame: &[u8]) -> Result<(), OutputError> {
+    let format = ImageFormat::from_path(path)?;
+    let expected_len = (width as usize) * (height as usize) * 4;
+    if frame.len() != expected_len {
+        return Err(OutputError::FrameSizeError(frame.len(), expected_len));
+    }
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        ImageFormat::PNG => {
+            let mut encoder = png::Encoder::new(&mut writer, width, height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.write_header()?.write_image_data(frame)?;
+        }
+        ImageFormat::PPM => {
+            // PPM has no alpha channel, so only the RGB part of every pixel is written.
+            write!(writer, "P6\n{} {}\n255\n", width, height)?;
+            for pixel in frame.chunks(4) {
+                writer.write_all(&pixel[..3])?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Path the AOV of the frame written to path goes to, frame.png gets frame.depth.pfm next to it.
+pub fn get_aov_path(path: &Path, aov: Aov) -> PathBuf {
+    path.with_extension(format!("{}.pfm", aov.get_name()))
+}
+
+// Writes aov of aov_frame to path as a little endian PFM, the float image format most compositors and denoisers read.
+pub fn save_aov(path: &Path, aov_frame: &AovFrame, aov: Aov) -> Result<(), OutputError> {
+    let width = aov_frame.get_width() as usize;
+    let channels = aov.get_channels();
+    let values = aov_frame.get_aov(aov);
+    let mut writer = BufWriter::new(File::create(path)?);
+    // PF is color and Pf greyscale, the negative scale marks the floats as little endian.
+    let kind = match channels {
+        1 => "Pf",
+        _ => "PF",
+    };
+    write!(writer, "{}\n{} {}\n-1.0\n", kind, aov_frame.get_width(), aov_frame.get_height())?;
+    // PFM stores the bottom row first.
+    for row in values.chunks(width * channels).rev() {
+        for value in row.iter() {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Renders a single frame of world as seen by camera and writes it to path, without needing a window.
+// The frame is rendered in tiles of at most tile_size x tile_size pixels, calling progress after every tile.
+// When aovs are asked for, each of them is written next to it, see get_aov_path.
+pub fn render_to_file(renderer: &mut Renderer, camera: Camera, world: &mut World, path: &Path, tile_size: u32, aovs: &[Aov], progress: &mut dyn FnMut(&TileProgress)) -> Result<(), OutputError> {
+    // Fail on an unknown extension before spending time on the render.
+    ImageFormat::from_path(path)?;
+    let render_objects = world.get_render_objects();
+    let directionlight_direction = world.get_direction_light_direction_vec();
+    let directionlight_color = world.get_direction_light_color_vec();
+    if aovs.is_empty() {
+        let frame = renderer.render_frame_tiled(camera, render_objects, directionlight_direction, directionlight_color, tile_size, progress)?;
+        return save_frame(path, renderer.get_width(), renderer.get_height(), &frame);
+    }
+    renderer.set_aovs(true);
+    let (frame, aov_frame) = renderer.render_frame_tiled_with_aovs(camera, render_objects, directionlight_direction, directionlight_color, tile_size, progress)?;
+    save_frame(path, renderer.get_width(), renderer.get_height(), &frame)?;
+    for aov in aovs.iter() {
+        save_aov(&get_aov_path(path, *aov), &aov_frame, *aov)?;
+    }
+    Ok(())
+}
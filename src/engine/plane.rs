@@ -0,0 +1,59 @@
+use crate::engine::cframe::{CFrame, Positionable};
+use crate::engine::render::{Renderable, RenderObject, RenderType, Intersection};
+use crate::engine::backends::cpu::{intersect_plane, ray_cframe};
+
+// Infinite plane through the position of its cframe. It faces along the up vector of the cframe, so without
+// any rotation it's a floor.
+#[derive(Default)]
+pub struct Plane {
+    cframe: CFrame,
+    end_cframe: Option<CFrame>,
+    color: Vec<u8>,
+}
+
+impl Plane {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+         }
+    }
+}
+
+impl Renderable for Plane {
+    fn get_render_object(&mut self) -> RenderObject {
+        let mut render_object = RenderObject::new(self.cframe, RenderType::PLANE, Vec::new(), self.color.clone());
+        if let Some(end_cframe) = self.end_cframe {
+            render_object.set_end_cframe(end_cframe);
+        }
+        render_object
+    }
+
+    fn set_color(&mut self, red: u8, green: u8, blue: u8) {
+        self.color = vec![red, green, blue];
+    }
+
+    fn intersect(&mut self, origin: &[f32; 3], direction: &[f32; 3]) -> Option<Intersection> {
+        let t = intersect_plane(&self.cframe.to_vec(), &ray_cframe(origin, direction));
+        if t <= 0f32 {
+            return None;
+        }
+        Some(Intersection {
+            distance: t,
+            normal: [self.cframe.r10, self.cframe.r11, self.cframe.r12],
+        })
+    }
+}
+
+impl Positionable for Plane {
+    fn set_cframe(&mut self, cframe: CFrame) {
+        self.cframe = cframe;
+    }
+
+    fn set_position(&mut self, x: f32, y: f32, z: f32) {
+        self.cframe = CFrame::new(x, y, z, self.cframe.r00, self.cframe.r01, self.cframe.r02, self.cframe.r10, self.cframe.r11, self.cframe.r12, self.cframe.r20, self.cframe.r21, self.cframe.r22);
+    }
+
+    fn set_end_cframe(&mut self, end_cframe: Option<CFrame>) {
+        self.end_cframe = end_cframe;
+    }
+}
\ No newline at end of file
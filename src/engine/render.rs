@@ -3,17 +3,37 @@ use crate::engine::cframe::CFrame;
 pub trait Renderable {
     fn get_render_object(&mut self) -> RenderObject;
     fn set_color(&mut self, red: u8, green: u8, blue: u8);
+    // Nearest hit in front of origin of the ray along the normalized direction, with the same math as the
+    // render kernel. Uses the cframe the object has when the shutter opens.
+    fn intersect(&mut self, origin: &[f32; 3], direction: &[f32; 3]) -> Option<Intersection>;
+}
+
+// Where a ray hits a Renderable.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Intersection {
+    // Along the ray, from its origin.
+    pub distance: f32,
+    // World space, pointing out of the object.
+    pub normal: [f32; 3],
 }
 
 #[derive(Copy, Clone)]
 pub enum RenderType {
     SPHERE = 0,
+    PLANE = 1,
+    CUBOID = 2,
+    MESH = 3,
 }
 
+#[derive(Clone)]
 pub struct RenderObject {
     cframe: CFrame,
+    // Where the object is at the end of the shutter interval, the same as cframe when it doesn't move.
+    end_cframe: CFrame,
     render_type: RenderType,
     object_props: Vec<f32>,
+    // Parameters that are whole numbers, like counts and indices, which floats can't hold exactly.
+    object_uint_props: Vec<u32>,
     color: Vec<u8>,
 }
 
@@ -21,25 +41,199 @@ impl RenderObject {
     pub fn new(cframe: CFrame, render_type: RenderType, object_props: Vec<f32>, color: Vec<u8>) -> Self {
         Self {
             cframe,
+            end_cframe: cframe,
             render_type,
             object_props,
+            object_uint_props: Vec::new(),
             color,
          }
     }
 
     pub fn convert_to_cframe_buffer(&mut self) -> Vec<f32> {
-        return self.cframe.to_vec();
+        self.cframe.to_vec()
+    }
+
+    pub fn set_end_cframe(&mut self, end_cframe: CFrame) {
+        self.end_cframe = end_cframe;
+    }
+
+    pub fn convert_to_end_cframe_buffer(&mut self) -> Vec<f32> {
+        self.end_cframe.to_vec()
     }
 
     pub fn get_render_type(&mut self) -> u8 {
-        return self.render_type as u8;
+        self.render_type as u8
     }
 
     pub fn get_object_props_vec(&mut self) -> Vec<f32> {
-        return self.object_props.clone();
+        self.object_props.clone()
+    }
+
+    pub fn set_object_uint_props(&mut self, object_uint_props: Vec<u32>) {
+        self.object_uint_props = object_uint_props;
+    }
+
+    pub fn get_object_uint_props_vec(&mut self) -> Vec<u32> {
+        self.object_uint_props.clone()
     }
 
     pub fn get_color_vec(&mut self) -> Vec<u8> {
-        return self.color.clone();
+        self.color.clone()
+    }
+}
+
+// Flattened scene layout shared by all render backends, matching the buffers the render kernel expects.
+#[derive(Default, Clone, PartialEq)]
+pub struct PackedScene {
+    pub cframes: Vec<f32>,
+    pub end_cframes: Vec<f32>,
+    // RenderType of every object.
+    pub render_types: Vec<u8>,
+    // Parameters of every object one after the other, each type has its own amount of them.
+    pub object_props: Vec<f32>,
+    // Where the parameters of every object start in object_props.
+    pub prop_offsets: Vec<u32>,
+    // Whole number parameters of every object one after the other, most types have none.
+    pub object_uint_props: Vec<u32>,
+    // Where the whole number parameters of every object start in object_uint_props.
+    pub uint_prop_offsets: Vec<u32>,
+    pub colors: Vec<u8>,
+    pub object_amnt: u32,
+}
+
+impl PackedScene {
+    pub fn new(render_objects: &mut [RenderObject]) -> Self {
+        let mut cframes = Vec::<f32>::new();
+        let mut end_cframes = Vec::<f32>::new();
+        let mut render_types = Vec::<u8>::new();
+        let mut object_props = Vec::<f32>::new();
+        let mut prop_offsets = Vec::<u32>::new();
+        let mut object_uint_props = Vec::<u32>::new();
+        let mut uint_prop_offsets = Vec::<u32>::new();
+        let mut colors = Vec::<u8>::new();
+        for obj in render_objects.iter_mut() {
+            cframes.extend(obj.convert_to_cframe_buffer());
+            end_cframes.extend(obj.convert_to_end_cframe_buffer());
+            render_types.push(obj.get_render_type());
+            prop_offsets.push(object_props.len() as u32);
+            object_props.extend(obj.get_object_props_vec());
+            uint_prop_offsets.push(object_uint_props.len() as u32);
+            object_uint_props.extend(obj.get_object_uint_props_vec());
+            // Every object takes exactly 3 bytes of colors, objects without a colour are white.
+            let mut color = obj.get_color_vec();
+            color.resize(3, 0xff);
+            colors.extend(color);
+        }
+        let object_amnt = (cframes.len() / 12) as u32;
+        Self {
+            cframes,
+            end_cframes,
+            render_types,
+            object_props,
+            prop_offsets,
+            object_uint_props,
+            uint_prop_offsets,
+            colors,
+            object_amnt,
+         }
+    }
+
+    // Start and end of the parameters of the object at index in object_props.
+    pub fn get_prop_range(&self, index: usize) -> (usize, usize) {
+        let start = self.prop_offsets[index] as usize;
+        let end = match self.prop_offsets.get(index + 1) {
+            Some(offset) => *offset as usize,
+            None => self.object_props.len(),
+        };
+        (start, end)
+    }
+
+    pub fn get_object_props(&self, index: usize) -> &[f32] {
+        let (start, end) = self.get_prop_range(index);
+        &self.object_props[start..end]
+    }
+
+    // Start and end of the whole number parameters of the object at index in object_uint_props.
+    pub fn get_uint_prop_range(&self, index: usize) -> (usize, usize) {
+        let start = self.uint_prop_offsets[index] as usize;
+        let end = match self.uint_prop_offsets.get(index + 1) {
+            Some(offset) => *offset as usize,
+            None => self.object_uint_props.len(),
+        };
+        (start, end)
+    }
+
+    pub fn get_object_uint_props(&self, index: usize) -> &[u32] {
+        let (start, end) = self.get_uint_prop_range(index);
+        &self.object_uint_props[start..end]
+    }
+
+    // Whether the object at index is packed identically in both scenes, including where its parameters are.
+    // Objects missing from either scene never match.
+    pub fn object_equals(&self, other: &PackedScene, index: usize) -> bool {
+        if index >= self.object_amnt as usize || index >= other.object_amnt as usize {
+            return false;
+        }
+        self.cframes[index * 12..(index + 1) * 12] == other.cframes[index * 12..(index + 1) * 12]
+            && self.end_cframes[index * 12..(index + 1) * 12] == other.end_cframes[index * 12..(index + 1) * 12]
+            && self.render_types[index] == other.render_types[index]
+            && self.get_prop_range(index) == other.get_prop_range(index)
+            && self.get_object_props(index) == other.get_object_props(index)
+            && self.get_uint_prop_range(index) == other.get_uint_prop_range(index)
+            && self.get_object_uint_props(index) == other.get_object_uint_props(index)
+            && self.colors[index * 3..(index + 1) * 3] == other.colors[index * 3..(index + 1) * 3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sphere(x: f32, radius: f32) -> RenderObject {
+        RenderObject::new(CFrame::new_from_pos(x, 0.0, 0.0), RenderType::SPHERE, vec![radius], vec![255, 0, 0])
+    }
+
+    fn plane() -> RenderObject {
+        RenderObject::new(CFrame::default(), RenderType::PLANE, Vec::new(), vec![0, 255, 0])
+    }
+
+    #[test]
+    fn prop_ranges_follow_each_other() {
+        let scene = PackedScene::new(&mut [sphere(0.0, 1.0), plane(), sphere(5.0, 2.0)]);
+        assert_eq!(scene.object_amnt, 3);
+        assert_eq!(scene.get_prop_range(0), (0, 1));
+        assert_eq!(scene.get_prop_range(1), (1, 1));
+        assert_eq!(scene.get_prop_range(2), (1, 2));
+        assert_eq!(scene.get_object_props(2), &[2.0]);
+    }
+
+    #[test]
+    fn uint_prop_ranges_follow_each_other() {
+        let mut mesh = RenderObject::new(CFrame::default(), RenderType::MESH, vec![1.0, 2.0, 3.0], vec![0, 0, 255]);
+        mesh.set_object_uint_props(vec![4, 5]);
+        let scene = PackedScene::new(&mut [sphere(0.0, 1.0), mesh]);
+        assert_eq!(scene.get_prop_range(1), (1, 4));
+        assert_eq!(scene.get_uint_prop_range(0), (0, 0));
+        assert_eq!(scene.get_uint_prop_range(1), (0, 2));
+        assert_eq!(scene.get_object_uint_props(1), &[4, 5]);
+    }
+
+    #[test]
+    fn object_equals_only_compares_the_object_at_index() {
+        let scene = PackedScene::new(&mut [sphere(0.0, 1.0), sphere(5.0, 1.0)]);
+        assert!(scene.object_equals(&PackedScene::new(&mut [sphere(0.0, 1.0), sphere(6.0, 1.0)]), 0));
+        assert!(!scene.object_equals(&PackedScene::new(&mut [sphere(0.0, 1.0), sphere(6.0, 1.0)]), 1));
+        assert!(!scene.object_equals(&PackedScene::new(&mut [sphere(0.0, 1.0), sphere(5.0, 2.0)]), 1));
+        // The same parameters somewhere else in object_props don't match.
+        assert!(!scene.object_equals(&PackedScene::new(&mut [plane(), sphere(5.0, 1.0)]), 1));
+        assert!(!scene.object_equals(&PackedScene::new(&mut [sphere(0.0, 1.0)]), 1));
+    }
+
+    #[test]
+    fn objects_without_a_color_are_white() {
+        let uncolored = RenderObject::new(CFrame::default(), RenderType::PLANE, Vec::new(), Vec::new());
+        let scene = PackedScene::new(&mut [uncolored, sphere(0.0, 1.0)]);
+        assert_eq!(scene.colors, vec![255, 255, 255, 255, 0, 0]);
+        assert!(scene.object_equals(&scene, 1));
     }
 }
\ No newline at end of file
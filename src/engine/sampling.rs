@@ -0,0 +1,185 @@
+use std::str::FromStr;
+
+// Where the samples of a pixel are placed inside its filter footprint.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SamplePattern {
+    GRID = 0,
+    JITTERED = 1,
+    ROTATEDGRID = 2,
+}
+
+// How the samples of a pixel are weighted when they are averaged into its color.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ReconstructionFilter {
+    BOX = 0,
+    TENT = 1,
+    GAUSSIAN = 2,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct AntiAliasing {
+    pub samples_per_pixel: u32,
+    pub pattern: SamplePattern,
+    pub filter: ReconstructionFilter,
+}
+
+// A single sample per pixel, which renders exactly like the renderer did before anti-aliasing existed.
+impl Default for AntiAliasing {
+    fn default() -> AntiAliasing {
+        AntiAliasing {
+            samples_per_pixel: 1,
+            pattern: SamplePattern::JITTERED,
+            filter: ReconstructionFilter::BOX,
+        }
+    }
+}
+
+impl FromStr for SamplePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "grid" | "regular" => Ok(SamplePattern::GRID),
+            "jittered" | "jitter" | "stratified" => Ok(SamplePattern::JITTERED),
+            "rotated" | "rotated-grid" | "rgss" => Ok(SamplePattern::ROTATEDGRID),
+            _ => Err(format!("Unknown sample pattern '{}', expected 'grid', 'jittered' or 'rotated'", s)),
+        }
+    }
+}
+
+impl FromStr for ReconstructionFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "box" => Ok(ReconstructionFilter::BOX),
+            "tent" | "triangle" => Ok(ReconstructionFilter::TENT),
+            "gaussian" | "gauss" => Ok(ReconstructionFilter::GAUSSIAN),
+            _ => Err(format!("Unknown filter '{}', expected 'box', 'tent' or 'gaussian'", s)),
+        }
+    }
+}
+
+// The functions below mirror sample_position, filter_radius and filter_weight of the render kernel.
+
+pub fn hash_uint(x: u32) -> u32 {
+    let mut x = x;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+    x
+}
+
+// Uniform random number in [0, 1), advancing the per pixel random state.
+pub fn random_float(state: &mut u32) -> f32 {
+    *state = hash_uint(*state);
+    (*state >> 8) as f32 * (1f32 / 16777216f32)
+}
+
+impl SamplePattern {
+    // Sample position in [0, 1) x [0, 1) inside the pixel footprint.
+    pub fn sample_position(&self, sample: u32, samples_per_pixel: u32, rng_state: &mut u32) -> (f32, f32) {
+        // nx columns and ny rows cover the pixel, the last row holds the remaining samples spread over its full width.
+        let nx = (samples_per_pixel as f32).sqrt().ceil().max(1f32) as u32;
+        let ny = samples_per_pixel.div_ceil(nx).max(1);
+        let row = sample / nx;
+        let row_width = if row == ny - 1 { samples_per_pixel - nx * (ny - 1) } else { nx };
+        let cell_x = (sample % nx) as f32;
+        let cell_y = row as f32;
+        let width = row_width.max(1) as f32;
+        let height = ny as f32;
+        match self {
+            SamplePattern::JITTERED => {
+                let u = (cell_x + random_float(rng_state)) / width;
+                let v = (cell_y + random_float(rng_state)) / height;
+                (u, v)
+            }
+            SamplePattern::GRID => ((cell_x + 0.5) / width, (cell_y + 0.5) / height),
+            SamplePattern::ROTATEDGRID => {
+                let du = (cell_x + 0.5) / width - 0.5;
+                let dv = (cell_y + 0.5) / height - 0.5;
+                let ru = du * 0.8944272 - dv * 0.4472136 + 0.5;
+                let rv = du * 0.4472136 + dv * 0.8944272 + 0.5;
+                (ru - ru.floor(), rv - rv.floor())
+            }
+        }
+    }
+}
+
+impl ReconstructionFilter {
+    // Half width of the filter in pixels.
+    pub fn get_radius(&self) -> f32 {
+        match self {
+            ReconstructionFilter::BOX => 0.5,
+            ReconstructionFilter::TENT => 1.0,
+            ReconstructionFilter::GAUSSIAN => 1.5,
+        }
+    }
+
+    pub fn weight(&self, dx: f32, dy: f32) -> f32 {
+        match self {
+            ReconstructionFilter::BOX => 1.0,
+            ReconstructionFilter::TENT => (1f32 - dx.abs()).max(0f32) * (1f32 - dy.abs()).max(0f32),
+            ReconstructionFilter::GAUSSIAN => {
+                // Standard deviation of 0.5 pixels, shifted down to reach 0 at the filter radius.
+                let edge = (-2f32 * 1.5 * 1.5).exp();
+                ((-2f32 * dx * dx).exp() - edge).max(0f32) * ((-2f32 * dy * dy).exp() - edge).max(0f32)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(pattern: SamplePattern, samples_per_pixel: u32) -> Vec<(f32, f32)> {
+        let mut rng_state = hash_uint(samples_per_pixel);
+        (0..samples_per_pixel).map(|sample| pattern.sample_position(sample, samples_per_pixel, &mut rng_state)).collect()
+    }
+
+    #[test]
+    fn samples_stay_inside_the_pixel() {
+        for pattern in [SamplePattern::GRID, SamplePattern::JITTERED, SamplePattern::ROTATEDGRID] {
+            for samples_per_pixel in 1..=17 {
+                for (u, v) in positions(pattern, samples_per_pixel) {
+                    assert!((0f32..1f32).contains(&u) && (0f32..1f32).contains(&v), "{:?} placed a sample at ({}, {})", pattern, u, v);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn grid_rows_are_spread_over_the_full_width() {
+        assert_eq!(positions(SamplePattern::GRID, 1), vec![(0.5, 0.5)]);
+        assert_eq!(positions(SamplePattern::GRID, 4), vec![(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)]);
+        // The last row only has the one sample that's left, in the middle.
+        assert_eq!(positions(SamplePattern::GRID, 3), vec![(0.25, 0.25), (0.75, 0.25), (0.5, 0.75)]);
+        for samples_per_pixel in 1..=17 {
+            let positions = positions(SamplePattern::GRID, samples_per_pixel);
+            for (i, a) in positions.iter().enumerate() {
+                assert!(positions[i + 1..].iter().all(|b| a != b), "{} samples share a position", samples_per_pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn jittered_samples_stay_in_their_cell() {
+        for (sample, (u, v)) in positions(SamplePattern::JITTERED, 9).into_iter().enumerate() {
+            assert_eq!(((u * 3f32) as usize, (v * 3f32) as usize), (sample % 3, sample / 3));
+        }
+    }
+
+    #[test]
+    fn rotated_grid_has_a_sample_in_every_row_and_column() {
+        let positions = positions(SamplePattern::ROTATEDGRID, 4);
+        let mut columns: Vec<usize> = positions.iter().map(|(u, _)| (u * 4f32) as usize).collect();
+        let mut rows: Vec<usize> = positions.iter().map(|(_, v)| (v * 4f32) as usize).collect();
+        columns.sort();
+        rows.sort();
+        assert_eq!(columns, vec![0, 1, 2, 3]);
+        assert_eq!(rows, vec![0, 1, 2, 3]);
+    }
+}
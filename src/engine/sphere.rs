@@ -1,9 +1,11 @@
 use crate::engine::cframe::{CFrame, Positionable};
-use crate::engine::render::{Renderable, RenderObject, RenderType};
+use crate::engine::render::{Renderable, RenderObject, RenderType, Intersection};
+use crate::engine::backends::cpu::{intersect_sphere, ray_cframe};
 
 #[derive(Default)]
 pub struct Sphere {
     cframe: CFrame,
+    end_cframe: Option<CFrame>,
     radius: f32,
     color: Vec<u8>,
 }
@@ -19,12 +21,29 @@ impl Sphere {
 
 impl Renderable for Sphere {
     fn get_render_object(&mut self) -> RenderObject {
-        return RenderObject::new(self.cframe, RenderType::SPHERE, vec![self.radius], self.color.clone());
+        let mut render_object = RenderObject::new(self.cframe, RenderType::SPHERE, vec![self.radius], self.color.clone());
+        if let Some(end_cframe) = self.end_cframe {
+            render_object.set_end_cframe(end_cframe);
+        }
+        render_object
     }
 
     fn set_color(&mut self, red: u8, green: u8, blue: u8) {
         self.color = vec![red, green, blue];
     }
+
+    fn intersect(&mut self, origin: &[f32; 3], direction: &[f32; 3]) -> Option<Intersection> {
+        let t = intersect_sphere(&self.cframe.to_vec(), self.radius, &ray_cframe(origin, direction));
+        if t <= 0f32 {
+            return None;
+        }
+        let normal = [origin[0] + direction[0] * t - self.cframe.x, origin[1] + direction[1] * t - self.cframe.y, origin[2] + direction[2] * t - self.cframe.z];
+        let normal_size = ((normal[0] * normal[0]) + (normal[1] * normal[1]) + (normal[2] * normal[2])).sqrt();
+        Some(Intersection {
+            distance: t,
+            normal: [normal[0] / normal_size, normal[1] / normal_size, normal[2] / normal_size],
+        })
+    }
 }
 
 impl Positionable for Sphere {
@@ -35,4 +54,8 @@ impl Positionable for Sphere {
     fn set_position(&mut self, x: f32, y: f32, z: f32) {
         self.cframe = CFrame::new(x, y, z, self.cframe.r00, self.cframe.r01, self.cframe.r02, self.cframe.r10, self.cframe.r11, self.cframe.r12, self.cframe.r20, self.cframe.r21, self.cframe.r22);
     }
+
+    fn set_end_cframe(&mut self, end_cframe: Option<CFrame>) {
+        self.end_cframe = end_cframe;
+    }
 }
\ No newline at end of file
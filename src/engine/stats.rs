@@ -0,0 +1,109 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+// Where the time of a single frame went, as measured by the backend that rendered it.
+// On OpenCL the timings come from the device's own event profiling, so host overhead isn't included.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct FrameStats {
+    // Writing the changed part of the scene to the device.
+    pub upload_time: Duration,
+    // Rendering and tone mapping.
+    pub kernel_time: Duration,
+    // Reading the finished frame back to the host.
+    pub readback_time: Duration,
+    // Camera rays, zero when an accumulated image had already converged. The shadow ray of a hit isn't counted.
+    pub rays_traced: u64,
+    // Objects that changed since the last frame. The CPU backend has nothing to upload and counts every object it rendered.
+    pub objects_uploaded: usize,
+}
+
+impl FrameStats {
+    pub fn get_total_time(&self) -> Duration {
+        self.upload_time + self.kernel_time + self.readback_time
+    }
+
+    // Stats of a frame that was rendered in parts, each part by its own device working in parallel.
+    pub fn merge_parallel(parts: &[FrameStats]) -> FrameStats {
+        FrameStats {
+            upload_time: parts.iter().map(|part| part.upload_time).max().unwrap_or_default(),
+            kernel_time: parts.iter().map(|part| part.kernel_time).max().unwrap_or_default(),
+            readback_time: parts.iter().map(|part| part.readback_time).max().unwrap_or_default(),
+            rays_traced: parts.iter().map(|part| part.rays_traced).sum(),
+            objects_uploaded: parts.iter().map(|part| part.objects_uploaded).sum(),
+        }
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "upload {:.2?}, kernel {:.2?}, readback {:.2?}, {:.2} Mrays, {} objects uploaded",
+               self.upload_time, self.kernel_time, self.readback_time, self.rays_traced as f64 / 1e6, self.objects_uploaded)
+    }
+}
+
+// Averages the stats of the last window frames, which smooths out the jitter of single frames.
+pub struct StatsReporter {
+    window: usize,
+    frames: VecDeque<FrameStats>,
+}
+
+impl StatsReporter {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            frames: VecDeque::new(),
+         }
+    }
+
+    pub fn push(&mut self, stats: FrameStats) {
+        if self.frames.len() == self.window {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(stats);
+    }
+
+    pub fn get_frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn get_average(&self) -> FrameStats {
+        let count = self.frames.len().max(1);
+        FrameStats {
+            upload_time: self.frames.iter().map(|frame| frame.upload_time).sum::<Duration>() / count as u32,
+            kernel_time: self.frames.iter().map(|frame| frame.kernel_time).sum::<Duration>() / count as u32,
+            readback_time: self.frames.iter().map(|frame| frame.readback_time).sum::<Duration>() / count as u32,
+            rays_traced: self.frames.iter().map(|frame| frame.rays_traced).sum::<u64>() / count as u64,
+            objects_uploaded: self.frames.iter().map(|frame| frame.objects_uploaded).sum::<usize>() / count,
+        }
+    }
+}
+
+// Writes one line of stats per frame, so runs of different builds can be compared in a spreadsheet.
+pub struct StatsCsvWriter {
+    writer: BufWriter<File>,
+    frame_index: u64,
+}
+
+impl StatsCsvWriter {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "frame,upload_ms,kernel_ms,readback_ms,total_ms,rays_traced,objects_uploaded")?;
+        Ok(Self {
+            writer,
+            frame_index: 0,
+        })
+    }
+
+    pub fn write(&mut self, stats: &FrameStats) -> std::io::Result<()> {
+        writeln!(self.writer, "{},{:.3},{:.3},{:.3},{:.3},{},{}", self.frame_index,
+                 stats.upload_time.as_secs_f64() * 1e3, stats.kernel_time.as_secs_f64() * 1e3, stats.readback_time.as_secs_f64() * 1e3,
+                 stats.get_total_time().as_secs_f64() * 1e3, stats.rays_traced, stats.objects_uploaded)?;
+        self.frame_index += 1;
+        // Flushed every frame, so nothing is lost when the window is closed or the process killed.
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,92 @@
+use std::str::FromStr;
+use crate::engine::camera::Camera;
+
+// How the images of both eyes are combined into one frame.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum StereoLayout {
+    // Left eye on the left half of the frame, each squeezed to half the width.
+    SIDEBYSIDE = 1,
+    // Left eye on the top half of the frame, each squeezed to half the height.
+    TOPBOTTOM = 2,
+    // Dubois red/cyan anaglyph, both eyes on the whole frame.
+    ANAGLYPH = 3,
+}
+
+impl FromStr for StereoLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "side-by-side" | "sbs" => Ok(StereoLayout::SIDEBYSIDE),
+            "top-bottom" | "over-under" | "tb" => Ok(StereoLayout::TOPBOTTOM),
+            "anaglyph" | "red-cyan" => Ok(StereoLayout::ANAGLYPH),
+            _ => Err(format!("Unknown stereo layout '{}', expected 'side-by-side', 'top-bottom' or 'anaglyph'", s)),
+        }
+    }
+}
+
+// Two cameras next to each other, placed around the camera the frame is rendered with.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct StereoRig {
+    // Distance between the eyes in world units.
+    pub interocular_distance: f32,
+    // Distance in front of the camera where both eyes see the same image, things closer than this pop out
+    // of the screen. The eyes look parallel when it's 0.
+    pub convergence_distance: f32,
+    pub layout: StereoLayout,
+}
+
+impl StereoRig {
+    pub fn new(interocular_distance: f32, convergence_distance: f32, layout: StereoLayout) -> Self {
+        Self {
+            interocular_distance,
+            convergence_distance,
+            layout,
+         }
+    }
+
+    // Left and right eye for camera. They converge by shifting their image planes instead of turning
+    // towards each other, which would make the images drift apart vertically near the edges.
+    pub fn get_eye_cameras(&self, camera: &Camera) -> (Camera, Camera) {
+        let half_distance = self.interocular_distance / 2f32;
+        let shift = match self.convergence_distance > 0f32 {
+            true => half_distance / self.convergence_distance,
+            false => 0f32,
+        };
+        let mut left = *camera;
+        let lens_shift = left.get_lens_shift();
+        offset_eye(&mut left, -half_distance);
+        left.set_lens_shift(lens_shift + shift);
+        let mut right = *camera;
+        offset_eye(&mut right, half_distance);
+        right.set_lens_shift(lens_shift - shift);
+        (left, right)
+    }
+}
+
+// Moves the camera sideways, along with where it ends up when it moves during the shutter interval.
+fn offset_eye(camera: &mut Camera, x: f32) {
+    camera.cframe.multiply_vector(x, 0f32, 0f32);
+    if let Some(mut end_cframe) = camera.get_end_cframe() {
+        end_cframe.multiply_vector(x, 0f32, 0f32);
+        camera.set_end_cframe(Some(end_cframe));
+    }
+}
+
+// The cameras as the render kernel reads them: the left eye followed by the right one, which is
+// the camera itself twice without stereo. Returns the layout number the kernel expects next to it.
+pub fn stereo_camera_vec(camera: &mut Camera, stereo: &Option<StereoRig>) -> (Vec<f32>, u8) {
+    match stereo {
+        Some(stereo) => {
+            let (mut left, mut right) = stereo.get_eye_cameras(camera);
+            let mut vec = left.to_vec();
+            vec.extend(right.to_vec());
+            (vec, stereo.layout as u8)
+        }
+        None => {
+            let mut vec = camera.to_vec();
+            vec.extend(camera.to_vec());
+            (vec, 0)
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Tiles are rendered one at a time, so this bounds the device memory a render needs no matter the image size.
+pub const DEFAULT_TILE_SIZE: u32 = 512;
+
+// Rectangular region of a frame, in pixels from the top left corner.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+         }
+    }
+
+    pub fn get_pixel_count(&self) -> usize {
+        (self.width as usize) * (self.height as usize)
+    }
+}
+
+// Hands out the tiles of a render one at a time to whoever asks next, so several devices can share them.
+pub struct TileQueue<'a> {
+    tiles: &'a [Tile],
+    next_tile: AtomicUsize,
+}
+
+impl<'a> TileQueue<'a> {
+    pub fn new(tiles: &'a [Tile]) -> Self {
+        Self {
+            tiles,
+            next_tile: AtomicUsize::new(0),
+         }
+    }
+
+    pub fn next(&self) -> Option<Tile> {
+        self.tiles.get(self.next_tile.fetch_add(1, Ordering::Relaxed)).copied()
+    }
+
+    // Pixels of the largest tile, buffers of that size can hold any of them.
+    pub fn get_max_pixel_count(&self) -> usize {
+        self.tiles.iter().map(|tile| tile.get_pixel_count()).max().unwrap_or(0)
+    }
+
+    // Hands out no more tiles, e.g. once one of the devices failed.
+    pub fn stop(&self) {
+        self.next_tile.store(self.tiles.len(), Ordering::Relaxed);
+    }
+}
+
+pub struct TileProgress {
+    pub tile: Tile,
+    pub tiles_done: usize,
+    pub tile_count: usize,
+}
+
+// Splits a width x height frame into row major tiles of at most tile_size x tile_size pixels.
+pub fn split_into_tiles(width: u32, height: u32, tile_size: u32) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let mut tiles = Vec::new();
+    let mut y = 0;
+    while y < height {
+        let tile_height = tile_size.min(height - y);
+        let mut x = 0;
+        while x < width {
+            let tile_width = tile_size.min(width - x);
+            tiles.push(Tile::new(x, y, tile_width, tile_height));
+            x += tile_width;
+        }
+        y += tile_height;
+    }
+    tiles
+}
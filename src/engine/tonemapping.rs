@@ -0,0 +1,70 @@
+use std::str::FromStr;
+
+// How linear colors above 1.0 are squeezed into the displayable range.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ToneMapOperator {
+    CLAMP = 0,
+    REINHARD = 1,
+    ACES = 2,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    // In stops, every +1 doubles the brightness before tone mapping.
+    pub exposure: f32,
+}
+
+impl Default for ToneMapping {
+    fn default() -> ToneMapping {
+        ToneMapping {
+            operator: ToneMapOperator::CLAMP,
+            exposure: 0f32,
+        }
+    }
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "clamp" | "none" => Ok(ToneMapOperator::CLAMP),
+            "reinhard" => Ok(ToneMapOperator::REINHARD),
+            "aces" | "filmic" => Ok(ToneMapOperator::ACES),
+            _ => Err(format!("Unknown tone mapping '{}', expected 'clamp', 'reinhard' or 'aces'", s)),
+        }
+    }
+}
+
+// The functions below mirror tone_map_channel, linear_to_srgb and tone_map of the render kernel.
+
+impl ToneMapOperator {
+    pub fn apply(&self, c: f32) -> f32 {
+        match self {
+            ToneMapOperator::CLAMP => c.clamp(0f32, 1f32),
+            ToneMapOperator::REINHARD => c / (1f32 + c),
+            // ACES filmic curve fit by Krzysztof Narkowicz.
+            ToneMapOperator::ACES => ((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)).clamp(0f32, 1f32),
+        }
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        return 12.92 * c;
+    }
+    1.055 * c.powf(1f32 / 2.4) - 0.055
+}
+
+impl ToneMapping {
+    // Turns a linear RGBA pixel into 8 bit sRGB.
+    pub fn map_pixel(&self, hdr: &[f32], pixel: &mut [u8]) {
+        let scale = self.exposure.exp2();
+        for i in 0..3 {
+            let mapped = self.operator.apply((hdr[i] * scale).max(0f32)).clamp(0f32, 1f32);
+            pixel[i] = (linear_to_srgb(mapped) * 255f32 + 0.5) as u8;
+        }
+        pixel[3] = 0xff;
+    }
+}
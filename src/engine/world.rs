@@ -1,5 +1,38 @@
-use crate::engine::render::{Renderable, RenderObject};
+use crate::engine::render::{Renderable, RenderObject, PackedScene};
 use crate::engine::lights::directionlight::DirectionLight;
+use crate::engine::camera::Camera;
+use crate::engine::stereo::StereoRig;
+use crate::engine::backends::cpu::cast_primary_ray;
+
+// Identifies an object pushed into a World, which is also its object ID in the AOVs.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ObjectHandle(pub usize);
+
+// The object visible at a pixel, see World::pick.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PickResult {
+    pub handle: ObjectHandle,
+    // Along the ray, from where it left the camera.
+    pub distance: f32,
+    // Along the viewing direction of the camera, like the depth AOV.
+    pub depth: f32,
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+}
+
+// An object hit by World::raycast.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RaycastHit {
+    pub handle: ObjectHandle,
+    // Along the ray, from its origin.
+    pub distance: f32,
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+}
+
+// How far occluded keeps away from both of its points, so surfaces they lie on don't count as in the way.
+// The same as the self-intersection correction of the shadow rays in the render kernel.
+const OCCLUSION_MARGIN: f32 = 0.01f32;
 
 #[derive(Default)]
 pub struct World {
@@ -19,18 +52,146 @@ impl World {
         for obj in self.objects.iter_mut() {
             render_objects.push(obj.get_render_object());
         }
-        return render_objects;
+        render_objects
     }
 
-    pub fn push_renderable(&mut self, render_object: Box<dyn Renderable>) {
+    pub fn push_renderable(&mut self, render_object: Box<dyn Renderable>) -> ObjectHandle {
         self.objects.push(render_object);
+        ObjectHandle(self.objects.len() - 1)
+    }
+
+    pub fn get_object(&mut self, handle: ObjectHandle) -> Option<&mut Box<dyn Renderable>> {
+        self.objects.get_mut(handle.0)
+    }
+
+    // Nearest object hit by the ray from origin along direction, at most max_distance away. Computed on the CPU with
+    // the objects where they are when the shutter opens, like every other object query on World.
+    pub fn raycast(&mut self, origin: [f32; 3], direction: [f32; 3], max_distance: f32) -> Option<RaycastHit> {
+        let mut nearest: Option<RaycastHit> = None;
+        self.for_each_hit(origin, direction, max_distance, |hit| {
+            if nearest.is_none_or(|nearest| hit.distance < nearest.distance) {
+                nearest = Some(hit);
+            }
+        });
+        nearest
+    }
+
+    // Every object hit by the ray, nearest first. Each object is only reported where the ray first hits it.
+    pub fn raycast_all(&mut self, origin: [f32; 3], direction: [f32; 3], max_distance: f32) -> Vec<RaycastHit> {
+        let mut hits: Vec<RaycastHit> = Vec::new();
+        self.for_each_hit(origin, direction, max_distance, |hit| hits.push(hit));
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        hits
+    }
+
+    // Calls on_hit for every object the ray hits at most max_distance away, in the order of the objects.
+    fn for_each_hit<F: FnMut(RaycastHit)>(&mut self, origin: [f32; 3], direction: [f32; 3], max_distance: f32, mut on_hit: F) {
+        let length = (direction[0] * direction[0] + direction[1] * direction[1] + direction[2] * direction[2]).sqrt();
+        if length == 0f32 {
+            return;
+        }
+        let direction = [direction[0] / length, direction[1] / length, direction[2] / length];
+        for (index, obj) in self.objects.iter_mut().enumerate() {
+            if let Some(intersection) = obj.intersect(&origin, &direction) {
+                if intersection.distance <= max_distance {
+                    on_hit(RaycastHit {
+                        handle: ObjectHandle(index),
+                        distance: intersection.distance,
+                        position: [origin[0] + direction[0] * intersection.distance, origin[1] + direction[1] * intersection.distance, origin[2] + direction[2] * intersection.distance],
+                        normal: intersection.normal,
+                    });
+                }
+            }
+        }
+    }
+
+    // Whether any object is in the way between the points a and b, like a shadow ray would see it.
+    pub fn occluded(&mut self, a: [f32; 3], b: [f32; 3]) -> bool {
+        let direction = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let distance = (direction[0] * direction[0] + direction[1] * direction[1] + direction[2] * direction[2]).sqrt();
+        if distance <= 2f32 * OCCLUSION_MARGIN {
+            return false;
+        }
+        let direction = [direction[0] / distance, direction[1] / distance, direction[2] / distance];
+        let origin = [a[0] + direction[0] * OCCLUSION_MARGIN, a[1] + direction[1] * OCCLUSION_MARGIN, a[2] + direction[2] * OCCLUSION_MARGIN];
+        self.raycast(origin, direction, distance - 2f32 * OCCLUSION_MARGIN).is_some()
+    }
+
+    // Object visible at the point (x, y) of a width x height frame rendered with camera, in pixels from its top
+    // left corner, so x + 0.5 is the centre of pixel x. Traced on the CPU along the same primary ray as the
+    // render kernel, through the centre of the lens when the shutter opens. None when nothing is hit.
+    pub fn pick(&mut self, camera: &mut Camera, x: f32, y: f32, width: u32, height: u32) -> Option<PickResult> {
+        self.pick_with_stereo(camera, &None, x, y, width, height)
+    }
+
+    // Like pick for frames rendered with a stereo rig, using the eye that shows the point. Anaglyphs use the left eye.
+    pub fn pick_with_stereo(&mut self, camera: &mut Camera, stereo: &Option<StereoRig>, x: f32, y: f32, width: u32, height: u32) -> Option<PickResult> {
+        let scene = PackedScene::new(&mut self.get_render_objects());
+        let hit = cast_primary_ray(camera, stereo, &scene, x, y, width, height)?;
+        Some(PickResult {
+            handle: ObjectHandle(hit.object_index),
+            distance: hit.distance,
+            depth: hit.depth,
+            position: hit.position,
+            normal: hit.normal,
+        })
     }
 
     pub fn get_direction_light_direction_vec(&mut self) -> Vec<f32> {
-        return self.directionlight.get_direction();
+        self.directionlight.get_direction()
     }
 
     pub fn get_direction_light_color_vec(&mut self) -> Vec<u8> {
-        return self.directionlight.get_color();
+        self.directionlight.get_color()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::sphere::Sphere;
+    use crate::engine::plane::Plane;
+    use crate::engine::cframe::Positionable;
+
+    fn sphere_at(z: f32, radius: f32) -> Box<Sphere> {
+        let mut sphere = Sphere::new(radius);
+        sphere.set_position(0.0, 0.0, z);
+        Box::new(sphere)
+    }
+
+    #[test]
+    fn raycast_returns_the_nearest_hit() {
+        let mut world = World::new();
+        let far = world.push_renderable(sphere_at(-20.0, 2.0));
+        let near = world.push_renderable(sphere_at(-10.0, 2.0));
+        let hit = world.raycast([0.0; 3], [0.0, 0.0, -3.0], f32::INFINITY).unwrap();
+        assert_eq!(hit.handle, near);
+        assert_eq!(hit.distance, 8.0);
+        assert_eq!(hit.position, [0.0, 0.0, -8.0]);
+        assert_eq!(hit.normal, [0.0, 0.0, 1.0]);
+        let hits = world.raycast_all([0.0; 3], [0.0, 0.0, -1.0], f32::INFINITY);
+        assert_eq!(hits.iter().map(|hit| hit.handle).collect::<Vec<ObjectHandle>>(), vec![near, far]);
+    }
+
+    #[test]
+    fn raycast_stops_at_max_distance() {
+        let mut world = World::new();
+        world.push_renderable(sphere_at(-10.0, 2.0));
+        assert!(world.raycast([0.0; 3], [0.0, 0.0, -1.0], 7.0).is_none());
+        assert!(world.raycast([0.0; 3], [0.0, 0.0, -1.0], 8.0).is_some());
+        assert!(world.raycast([0.0; 3], [0.0, 0.0, 1.0], f32::INFINITY).is_none());
+        assert!(world.raycast([0.0; 3], [0.0; 3], f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn occluded_ignores_the_surfaces_of_its_points() {
+        let mut world = World::new();
+        let mut floor = Plane::new();
+        floor.set_position(0.0, -1.0, 0.0);
+        world.push_renderable(Box::new(floor));
+        world.push_renderable(sphere_at(-10.0, 2.0));
+        assert!(world.occluded([0.0, 0.0, 0.0], [0.0, 0.0, -20.0]));
+        assert!(!world.occluded([0.0, -1.0, 0.0], [5.0, -1.0, 0.0]));
+        assert!(!world.occluded([0.0, 0.0, -8.0], [0.0, 0.0, 0.0]));
     }
 }
\ No newline at end of file
@@ -8,52 +8,95 @@ use winit_input_helper::WinitInputHelper;
 use log::error;
 use error_iter::ErrorIter as _;
 use std::time::Instant;
-mod engine;
-use crate::engine::renderer::Renderer;
-use crate::engine::camera::Camera;
-use crate::engine::world::World;
-use crate::engine::sphere::Sphere;
-use crate::engine::cframe::Positionable;
-use crate::engine::render::Renderable;
+mod cli;
+use crate::cli::Options;
+use first_test::engine::renderer::{Renderer, BackendType, MAX_FRAMES_IN_FLIGHT};
+use first_test::engine::backends::devices::list_devices;
+use first_test::engine::camera::Camera;
+use first_test::engine::world::World;
+use first_test::engine::sphere::Sphere;
+use first_test::engine::plane::Plane;
+use first_test::engine::cuboid::Cuboid;
+use first_test::engine::mesh::Mesh;
+use first_test::engine::cframe::{CFrame, Positionable};
+use first_test::engine::render::Renderable;
+use first_test::engine::tiling::TileProgress;
+use first_test::engine::output::render_to_file;
+use first_test::engine::stats::{StatsReporter, StatsCsvWriter};
 
-const WIDTH: u32 = 1280;
-const HEIGHT: u32 = 720;
 const CAMERA_MOVE_SPEED: f32 = 0.3;
 const CAMERA_ROTATE_SPEED: f32 = 0.001;
+// Frames the printed stats are averaged over.
+const STATS_WINDOW: usize = 60;
 
 fn main() -> Result<(), Error> {
     env_logger::init();
-    let mut renderer = Renderer::new(WIDTH, HEIGHT);
+    let options = Options::from_args().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, cli::USAGE);
+        std::process::exit(1);
+    });
+    if options.list_devices {
+        print_devices();
+        return Ok(());
+    }
+    let mut renderer = match options.backend {
+        BackendType::OPENCL => Renderer::new_with_kernel_source(options.width, options.height, options.device_selector.clone(), options.kernel_source.clone()),
+        BackendType::MULTI => Renderer::new_with_devices(options.width, options.height, options.device_selectors.clone(), options.kernel_source.clone()),
+        _ => Renderer::new_with_backend(options.width, options.height, options.backend),
+    };
     renderer.init().expect("Failed to initialize renderer");
+    renderer.set_anti_aliasing(options.anti_aliasing);
+    renderer.set_tone_mapping(options.tone_mapping);
+    renderer.set_stereo(options.stereo);
+    log::info!("Rendering with the {:?} backend", renderer.get_backend_type());
+    let mut camera = options.camera;
+    let mut world = build_demo_world();
+    if options.autofocus {
+        focus_on_centre(&mut camera, &mut world, renderer.get_width(), renderer.get_height());
+    }
+
+    if let Some(path) = options.output {
+        let mut report_progress = |progress: &TileProgress| {
+            let tile = progress.tile;
+            log::info!("Rendered tile {}/{} ({}x{} at {}, {})", progress.tiles_done, progress.tile_count, tile.width, tile.height, tile.x, tile.y);
+        };
+        if let Err(err) = render_to_file(&mut renderer, camera, &mut world, &path, options.tile_size, &options.aovs, &mut report_progress) {
+            log_error("render_to_file", err);
+            std::process::exit(1);
+        }
+        println!("Wrote {}", path.display());
+        return Ok(());
+    }
+
+    renderer.set_accumulation(true);
+    let shutter = options.shutter;
+    let mut stats_reporter = StatsReporter::new(STATS_WINDOW);
+    let mut stats_csv = match options.stats_csv {
+        Some(path) => match StatsCsvWriter::create(&path) {
+            Ok(stats_csv) => Some(stats_csv),
+            Err(err) => {
+                log_error("StatsCsvWriter::create", err);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
     let event_loop = EventLoop::new().unwrap();
     let mut input = WinitInputHelper::new();
-    let mut now = Instant::now();
-    let mut camera = Camera::new(90f32, 0.1f32);
-    let mut world = World::new();
-    let mut sphere = Sphere::new(10f32);
-    let mut sphere2 = Sphere::new(10f32);
-    let mut floor = Sphere::new(100000f32);
-    sphere.set_position(-10f32, 15f32, -70f32);
-    sphere2.set_position(15f32, 5f32, -70f32);
-    floor.set_position(0f32, -100002f32, 0f32);
-    sphere.set_color(0xffu8, 0x00u8, 0x00u8);
-    sphere2.set_color(0x00u8, 0xffu8, 0x00u8);
-    floor.set_color(0x00u8, 0x00u8, 0xffu8);
-    world.push_renderable(Box::new(sphere));
-    world.push_renderable(Box::new(sphere2));
-    world.push_renderable(Box::new(floor));
-    let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
+    // When the current stats window started, and how many frames were presented in it.
+    let mut window_start = Instant::now();
+    let mut window_frames = 0;
+    let size = LogicalSize::new(options.width as f64, options.height as f64);
     let window = WindowBuilder::new()
         .with_title("Simple ray tracer")
         .with_inner_size(size)
-        .with_min_inner_size(size)
         .build(&event_loop)
         .unwrap();
 
     let mut pixels = {
         let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(WIDTH.into(), HEIGHT.into(), surface_texture)?
+        Pixels::new(options.width, options.height, surface_texture)?
     };
 
     let mut forward = 0f32;
@@ -61,6 +104,8 @@ fn main() -> Result<(), Error> {
     let mut clicked = false;
     let mut cursor_side = 0f32;
     let mut cursor_top = 0f32;
+    // Cursor position in pixels of the frame, which is rendered at the logical size of the window.
+    let mut cursor_position = (0f32, 0f32);
 
     event_loop.run(move |event: Event<()>, event_loop| {
         // Draw the current frame
@@ -78,6 +123,31 @@ fn main() -> Result<(), Error> {
         if let Event::WindowEvent { event, .. } = event {
             match event {
                 WindowEvent::CloseRequested => event_loop.exit(),
+                WindowEvent::Resized(window_size) => {
+                    // Minimizing resizes the window to nothing, keep the last frame size until it comes back.
+                    if window_size.width == 0 || window_size.height == 0 {
+                        return;
+                    }
+                    // Render at the logical size so the resolution doesn't jump on high DPI screens.
+                    let frame_size = window_size.to_logical::<u32>(window.scale_factor());
+                    let (width, height) = (frame_size.width.max(1), frame_size.height.max(1));
+                    if let Err(err) = pixels.resize_surface(window_size.width, window_size.height) {
+                        log_error("pixels.resize_surface", err);
+                        event_loop.exit();
+                        return;
+                    }
+                    if let Err(err) = pixels.resize_buffer(width, height) {
+                        log_error("pixels.resize_buffer", err);
+                        event_loop.exit();
+                        return;
+                    }
+                    if let Err(err) = renderer.resize(width, height) {
+                        log_error("renderer.resize", err);
+                        event_loop.exit();
+                        return;
+                    }
+                    window.request_redraw();
+                }
                 WindowEvent::KeyboardInput {
                     event:
                         KeyEvent {
@@ -174,28 +244,87 @@ fn main() -> Result<(), Error> {
                         to_side = 0f32;
                     }
                 }
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            physical_key: PhysicalKey::Code(KeyCode::KeyF),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    focus_on_centre(&mut camera, &mut world, renderer.get_width(), renderer.get_height());
+                }
                 WindowEvent::MouseInput {
                     device_id, state, button
                 } => {
                     if button == MouseButton::Left {
                         clicked = state == ElementState::Pressed;
                     }
+                    if button == MouseButton::Right && state == ElementState::Pressed {
+                        pick_under_cursor(&renderer, &mut camera, &mut world, cursor_position);
+                    }
+                }
+                WindowEvent::CursorMoved {
+                    position, ..
+                } => {
+                    let position = position.to_logical::<f32>(window.scale_factor());
+                    cursor_position = (position.x, position.y);
                 }
                 WindowEvent::RedrawRequested => {
+                    let previous_cframe = camera.cframe;
                     let mut movesize = (forward * forward + to_side * to_side).sqrt().max(1.0f32);
                     camera.cframe.multiply_vector(to_side / movesize * CAMERA_MOVE_SPEED, 0f32, forward / movesize * CAMERA_MOVE_SPEED);
                     if clicked {
                         camera.cframe.multiply_angles(cursor_top * CAMERA_ROTATE_SPEED, cursor_side * CAMERA_ROTATE_SPEED, 0f32);
                     }
-                    let elapsed = now.elapsed();
-                    println!("Elapsed: {:.2?}", elapsed);
-                    now = Instant::now();
+                    // Only rotate by mouse movement that happened since the last frame, so holding the button
+                    // without moving leaves the camera still and lets the image accumulate.
+                    cursor_side = 0f32;
+                    cursor_top = 0f32;
                     let render_objects = world.get_render_objects();
                     let directionlight_direction = world.get_direction_light_direction_vec();
                     let directionlight_color = world.get_direction_light_color_vec();
-                    let mut vec = renderer.render_frame(camera, render_objects, directionlight_direction, directionlight_color).expect("failed to render frame");
-                    let mut frame = pixels.frame_mut();
-                    frame.copy_from_slice(&mut vec[..]);
+                    // The shutter closes where the camera is now and opened the given part of the movement since the last frame earlier.
+                    let mut frame_camera = camera;
+                    if shutter > 0f32 {
+                        frame_camera.cframe = previous_cframe.interpolate(&camera.cframe, 1f32 - shutter);
+                        frame_camera.set_end_cframe(Some(camera.cframe));
+                    }
+                    if let Err(err) = renderer.submit_frame(frame_camera, render_objects, directionlight_direction, directionlight_color) {
+                        log_error("renderer.submit_frame", err);
+                        event_loop.exit();
+                        return;
+                    }
+                    // Present the previous frame while the device works on the one that was just submitted.
+                    if renderer.get_frames_in_flight() >= MAX_FRAMES_IN_FLIGHT {
+                        match renderer.wait_frame_with_stats() {
+                            Ok(Some((vec, stats))) => {
+                                pixels.frame_mut().copy_from_slice(&vec[..]);
+                                stats_reporter.push(stats);
+                                window_frames += 1;
+                                if window_frames == STATS_WINDOW {
+                                    let fps = window_frames as f32 / window_start.elapsed().as_secs_f32();
+                                    println!("Last {} frames: {:.1} fps, {} ({} samples)", window_frames, fps, stats_reporter.get_average(), renderer.get_accumulated_samples());
+                                    window_start = Instant::now();
+                                    window_frames = 0;
+                                }
+                                if let Some(stats_csv) = stats_csv.as_mut() {
+                                    if let Err(err) = stats_csv.write(&stats) {
+                                        log_error("stats_csv.write", err);
+                                        event_loop.exit();
+                                        return;
+                                    }
+                                }
+                            }
+                            Ok(None) => (),
+                            Err(err) => {
+                                log_error("renderer.wait_frame", err);
+                                event_loop.exit();
+                                return;
+                            }
+                        }
+                    }
                     // world.draw(pixels.frame_mut());
                     if let Err(err) = pixels.render() {
                         log_error("pixels.render", err);
@@ -212,6 +341,72 @@ fn main() -> Result<(), Error> {
     Ok(())
 }
 
+fn print_devices() {
+    match list_devices() {
+        Ok(devices) => {
+            let mut platform_index = None;
+            for device in devices.iter() {
+                if platform_index != Some(device.platform_index) {
+                    println!("Platform [{}] {}", device.platform_index, device.platform_name);
+                    platform_index = Some(device.platform_index);
+                }
+                println!("  {}", device);
+            }
+            if devices.is_empty() {
+                println!("No OpenCL devices found");
+            }
+        }
+        Err(err) => log_error("list_devices", err),
+    }
+}
+
+fn focus_on_centre(camera: &mut Camera, world: &mut World, width: u32, height: u32) {
+    match camera.autofocus(world, width / 2, height / 2, width, height) {
+        Some(distance) => log::info!("Focused at {:.2}", distance),
+        None => log::info!("Nothing to focus on in the centre of the frame"),
+    }
+}
+
+fn pick_under_cursor(renderer: &Renderer, camera: &mut Camera, world: &mut World, cursor_position: (f32, f32)) {
+    match renderer.pick(camera, world, cursor_position.0, cursor_position.1) {
+        Some(pick) => log::info!("Picked object {} at {:.2} units, position {:?}, normal {:?}", pick.handle.0, pick.distance, pick.position, pick.normal),
+        None => log::info!("Nothing under the cursor"),
+    }
+}
+
+fn build_demo_world() -> World {
+    let mut world = World::new();
+    let mut sphere = Sphere::new(10f32);
+    let mut sphere2 = Sphere::new(10f32);
+    let mut floor = Plane::new();
+    let mut cuboid = Cuboid::new(4f32, 4f32, 4f32);
+    // Square pyramid standing on the floor, its apex straight above the centre of the base.
+    let mut pyramid = Mesh::new(
+        vec![[-5f32, 0f32, -5f32], [5f32, 0f32, -5f32], [5f32, 0f32, 5f32], [-5f32, 0f32, 5f32], [0f32, 8f32, 0f32]],
+        vec![[0, 1, 4], [1, 2, 4], [2, 3, 4], [3, 0, 4], [0, 2, 1], [0, 3, 2]],
+    ).expect("Demo pyramid has an invalid vertex index");
+    sphere.set_position(-10f32, 15f32, -70f32);
+    sphere2.set_position(15f32, 5f32, -70f32);
+    floor.set_position(0f32, -2f32, 0f32);
+    let mut cuboid_cframe = CFrame::new_from_pos(-25f32, 2f32, -55f32);
+    cuboid_cframe.multiply_angles(0f32, -0.5f32, 0f32);
+    cuboid.set_cframe(cuboid_cframe);
+    let mut pyramid_cframe = CFrame::new_from_pos(5f32, -2f32, -50f32);
+    pyramid_cframe.multiply_angles(0f32, 0.4f32, 0f32);
+    pyramid.set_cframe(pyramid_cframe);
+    sphere.set_color(0xffu8, 0x00u8, 0x00u8);
+    sphere2.set_color(0x00u8, 0xffu8, 0x00u8);
+    floor.set_color(0x00u8, 0x00u8, 0xffu8);
+    cuboid.set_color(0xffu8, 0xffu8, 0x00u8);
+    pyramid.set_color(0xffu8, 0x80u8, 0x00u8);
+    world.push_renderable(Box::new(sphere));
+    world.push_renderable(Box::new(sphere2));
+    world.push_renderable(Box::new(floor));
+    world.push_renderable(Box::new(cuboid));
+    world.push_renderable(Box::new(pyramid));
+    world
+}
+
 fn log_error<E: std::error::Error + 'static>(method_name: &str, err: E) {
     error!("{method_name}() failed: {err}");
     for source in err.sources().skip(1) {